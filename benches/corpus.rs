@@ -0,0 +1,54 @@
+// Criterion benchmarks of the compression and the decompression of the
+// generated corpora, run with "cargo bench".
+//
+// Each corpus is coded with the coder chosen for each block, like --coder
+// auto, and with the Huffman coder alone, like the default of the command
+// line. The zero filled corpus is a run of a single symbol for both.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use huffman_codes::coder::{Coder, CoderMode};
+use huffman_codes::corpus::{self, Corpus};
+use huffman_codes::CompressOptions;
+
+/// Size of each corpus, 1 MiB.
+const CORPUS_SIZE: usize = 1024 * 1024;
+
+/// The coder modes of the benchmarks, with their name in the results.
+const CODERS: [(&str, CoderMode); 2] = [
+    ("auto",    CoderMode::Auto),
+    ("huffman", CoderMode::Fixed(Coder::Huffman)),
+];
+
+fn bench_corpora(c: & mut Criterion) {
+    huffman_codes::set_quiet(true);
+
+    let mut group = c.benchmark_group("compress");
+    group.throughput(Throughput::Bytes(CORPUS_SIZE as u64));
+    for &(name, coder) in CODERS.iter() {
+        let options = CompressOptions { coder, ..CompressOptions::default() };
+        for kind in Corpus::ALL.iter() {
+            let buffer_in = corpus::generate(*kind, CORPUS_SIZE);
+            group.bench_with_input(BenchmarkId::new(name, kind), & buffer_in, |b, buffer_in| {
+                b.iter(|| huffman_codes::compress_buffer(buffer_in, & options))
+            });
+        }
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("decompress");
+    group.throughput(Throughput::Bytes(CORPUS_SIZE as u64));
+    for &(name, coder) in CODERS.iter() {
+        let options = CompressOptions { coder, ..CompressOptions::default() };
+        for kind in Corpus::ALL.iter() {
+            let buffer_johnny = huffman_codes::compress_buffer(& corpus::generate(*kind, CORPUS_SIZE), & options);
+            group.bench_with_input(BenchmarkId::new(name, kind), & buffer_johnny, |b, buffer_johnny| {
+                b.iter(|| huffman_codes::decompress_buffer(buffer_johnny, options.threads))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_corpora);
+criterion_main!(benches);
@@ -0,0 +1,17 @@
+// The decompression of any .johnny file, corrupt or cut short, must give the
+// message or an error, never a panic or a hang.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use huffman_codes::limits::Limits;
+
+/// The files that claim a bigger message are rejected by the limits, a few
+/// bytes of a run or of the range coder can code gigabytes of one symbol.
+const LIMITS: Limits = Limits { max_output: 16 * 1024 * 1024, ..Limits::NONE };
+
+fuzz_target!(|data: &[u8]| {
+    huffman_codes::set_quiet(true);
+    let _ = huffman_codes::decompress_buffer_with_limits(data, 1, None, & LIMITS);
+});
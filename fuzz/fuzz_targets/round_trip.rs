@@ -0,0 +1,52 @@
+// Any message, compressed with the options of the first bytes, must
+// decompress to the same message.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use huffman_codes::alphabet::Alphabet;
+use huffman_codes::coder::{Coder, CoderMode};
+use huffman_codes::filter::{Filter, FilterMode};
+use huffman_codes::CompressOptions;
+
+fuzz_target!(|data: &[u8]| {
+    huffman_codes::set_quiet(true);
+    if data.len() < 4 {
+        return;
+    }
+    let (options_bytes, message) = data.split_at(4);
+    let filter = match options_bytes[0] % 6 {
+        0 => Filter::None,
+        1 => Filter::Delta(options_bytes[0] / 6 % 4 + 1),
+        2 => Filter::Shuffle(options_bytes[0] / 6 % 8 + 1),
+        3 => Filter::X86,
+        4 => Filter::Arm64,
+        _ => Filter::None,
+    };
+    let alphabet = match options_bytes[1] % 4 {
+        0 => Alphabet::Bytes,
+        1 => Alphabet::U16,
+        2 => Alphabet::Pairs,
+        _ => Alphabet::Words,
+    };
+    let coder = match options_bytes[2] % 4 {
+        0 => CoderMode::Fixed(Coder::Huffman),
+        1 => CoderMode::Fixed(Coder::Arith),
+        2 => CoderMode::Fixed(Coder::Fse),
+        _ => CoderMode::Auto,
+    };
+    let options = CompressOptions {
+        filter: FilterMode::Fixed(filter),
+        alphabet,
+        coder,
+        block_size: 64 + options_bytes[3] as usize * 64,
+        streams: options_bytes[3] as usize % 16 + 1,
+        ..CompressOptions::default()
+    };
+
+    let buffer_johnny = huffman_codes::compress_buffer(message, & options);
+    let buffer_out = huffman_codes::decompress_buffer(& buffer_johnny, 1)
+        .expect("...a compressed message must decompress.");
+    assert!(buffer_out == message, "...the message doesn't round trip.");
+});
@@ -0,0 +1,14 @@
+// The Huffman mapping table and the .jtbl dictionary files, as read from a
+// file. Any bytes must give a table or an error, never a panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use huffman_codes::dictionary::Dictionary;
+
+fuzz_target!(|data: &[u8]| {
+    huffman_codes::set_quiet(true);
+    let _ = huffman_codes::parse_huffman_table(data);
+    let _ = Dictionary::from_bytes(data);
+});
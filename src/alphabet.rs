@@ -0,0 +1,420 @@
+// The alphabet of symbols that the Huffman code is made for.
+//
+//   bytes - The default, each byte of the message is a symbol, 256 symbols.
+//   u16   - Each 16 bit little-endian word is a symbol, up to 65536 symbols.
+//           Good for UTF-16 text and for binary formats made of 16 bit fields.
+//           If the message has an odd number of bytes, the last byte is kept
+//           in the alphabet data.
+//   pairs - The 256 byte values plus the most frequent byte pairs (digrams)
+//           of the message, chosen in a pre-pass. The message is converted to
+//           symbols from left to right, taking a pair whenever possible.
+//   words - For natural-language text. The message is split in tokens, words
+//           (with the space after them), runs of spaces and single punctuation
+//           bytes. The 256 byte values plus the tokens that repeat are the
+//           symbols, the tokens that don't repeat are coded byte by byte.
+//           The dictionary of tokens is sorted and front coded.
+//
+// The alphabet is recorded in the .johnny header, and the alphabet data
+// (the chosen pairs or the last odd byte) comes before the mapping table.
+
+use std::fmt;
+
+use hashbrown::HashMap;
+
+use crate::error::{bytes_at, Error, Result};
+
+/// The symbols of the message, the index in the alphabet.
+pub type Symbol = u32;
+
+/// Maximum number of byte pairs added to the alphabet.
+const MAX_PAIRS: usize = 256;
+
+/// A byte pair only goes into the alphabet if it appears at least this number
+/// of times, otherwise it's entry in the table costs more than it saves.
+const MIN_PAIR_COUNT: usize = 16;
+
+/// Maximum number of tokens in the dictionary of the words alphabet, the most
+/// frequent ones are kept, so the dictionary and the mapping table stay small.
+const MAX_WORDS: usize = 20_000;
+
+/// A token only goes into the dictionary if it appears at least this number of times.
+const MIN_WORD_COUNT: usize = 2;
+
+/// The length of the tokens is written in one byte.
+const MAX_WORD_LEN: usize = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Alphabet {
+    Bytes,
+    U16,
+    Pairs,
+    Words,
+}
+
+impl Alphabet {
+
+    /// Parses the command line alphabet, ex: "bytes", "u16", "pairs" or "words".
+    pub fn parse(text: &str) -> Option<Alphabet> {
+        match &text.to_ascii_lowercase()[..] {
+            "bytes" => Some(Alphabet::Bytes),
+            "u16"   => Some(Alphabet::U16),
+            "pairs" => Some(Alphabet::Pairs),
+            "words" => Some(Alphabet::Words),
+            _ => None,
+        }
+    }
+
+    pub fn to_header_byte(self) -> u8 {
+        match self {
+            Alphabet::Bytes => 0,
+            Alphabet::U16   => 1,
+            Alphabet::Pairs => 2,
+            Alphabet::Words => 3,
+        }
+    }
+
+    pub fn from_header_byte(id: u8) -> Option<Alphabet> {
+        match id {
+            0 => Some(Alphabet::Bytes),
+            1 => Some(Alphabet::U16),
+            2 => Some(Alphabet::Pairs),
+            3 => Some(Alphabet::Words),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Alphabet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Alphabet::Bytes => write!(f, "bytes"),
+            Alphabet::U16   => write!(f, "u16"),
+            Alphabet::Pairs => write!(f, "pairs"),
+            Alphabet::Words => write!(f, "words"),
+        }
+    }
+}
+
+/// The integer types that hold the symbols of the message, so that the bytes
+/// alphabet doesn't need a copy of the message with 4 bytes per symbol.
+pub trait SymbolType: Copy {
+    fn to_symbol(self) -> Symbol;
+    fn from_symbol(symbol: Symbol) -> Self;
+
+    /// The message as bytes, only for the bytes alphabet.
+    fn as_bytes(_buffer: &[Self]) -> Option<&[u8]> {
+        None
+    }
+}
+
+impl SymbolType for u8 {
+    fn to_symbol(self) -> Symbol { self as Symbol }
+    fn from_symbol(symbol: Symbol) -> Self { symbol as u8 }
+    fn as_bytes(buffer: &[u8]) -> Option<&[u8]> { Some(buffer) }
+}
+
+impl SymbolType for u16 {
+    fn to_symbol(self) -> Symbol { self as Symbol }
+    fn from_symbol(symbol: Symbol) -> Self { symbol as u16 }
+}
+
+impl SymbolType for u32 {
+    fn to_symbol(self) -> Symbol { self }
+    fn from_symbol(symbol: Symbol) -> Self { symbol }
+}
+
+//******************
+//* u16 alphabet
+//******************
+
+/// Converts the message to 16 bit symbols. Returns the symbols and the
+/// alphabet data, a flag byte and the last odd byte.
+pub fn to_u16_symbols(buffer_in: &[u8]) -> (Vec<u16>, Vec<u8>) {
+    let symbols: Vec<u16> = buffer_in.chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    let alphabet_data = if buffer_in.len() % 2 == 1 {
+        vec![1, buffer_in[buffer_in.len() - 1]]
+    } else {
+        vec![0]
+    };
+    (symbols, alphabet_data)
+}
+
+/// Reads the u16 alphabet data. Returns the last odd byte and the number of
+/// bytes used by the alphabet data.
+pub fn read_u16_alphabet_data(buffer_in: &[u8]) -> Result<(Option<u8>, usize)> {
+    match buffer_in {
+        [1, last_byte, ..] => Ok((Some(*last_byte), 2)),
+        [0, ..]            => Ok((None, 1)),
+        _ => Err(Error::BadTable("the data of the u16 alphabet isn't valid")),
+    }
+}
+
+pub fn from_u16_symbols(symbols: &[u16], last_byte: Option<u8>) -> Vec<u8> {
+    let mut buffer_out = Vec::with_capacity(symbols.len() * 2 + 1);
+    for symbol in symbols {
+        buffer_out.extend_from_slice(&symbol.to_le_bytes());
+    }
+    if let Some(byte) = last_byte {
+        buffer_out.push(byte);
+    }
+    buffer_out
+}
+
+//******************
+//* pairs alphabet
+//******************
+
+/// Pre-pass, counts all the byte pairs of the message and chooses the most
+/// frequent ones. The symbols 0 to 255 are the bytes and the symbols from 256
+/// are the chosen pairs.
+/// Returns the symbols, the alphabet size and the alphabet data, the number
+/// of pairs in two bytes followed by the two bytes of each pair.
+pub fn to_pair_symbols(buffer_in: &[u8]) -> (Vec<u16>, usize, Vec<u8>) {
+    let mut map_pair_freq: Vec<usize> = vec![0; 256 * 256];
+    for pair in buffer_in.windows(2) {
+        map_pair_freq[(pair[0] as usize) << 8 | pair[1] as usize] += 1;
+    }
+
+    let mut vec_pairs: Vec<(usize, usize)> = map_pair_freq.iter()
+        .enumerate()
+        .filter(|(_pair, count)| **count >= MIN_PAIR_COUNT)
+        .map(|(pair, count)| (pair, *count))
+        .collect();
+    // Most frequent first, and the pair value to break the ties.
+    vec_pairs.sort_by(|(pair_a, count_a), (pair_b, count_b)| count_b.cmp(count_a).then(pair_a.cmp(pair_b)));
+    vec_pairs.truncate(MAX_PAIRS);
+
+    // Maps each pair to it's symbol, 0 if the pair isn't in the alphabet.
+    let mut map_pair_symbol: Vec<u16> = vec![0; 256 * 256];
+    let mut alphabet_data: Vec<u8> = Vec::with_capacity(2 + vec_pairs.len() * 2);
+    alphabet_data.extend_from_slice(&(vec_pairs.len() as u16).to_be_bytes());
+    for (index, (pair, _count)) in vec_pairs.iter().enumerate() {
+        map_pair_symbol[*pair] = (256 + index) as u16;
+        alphabet_data.push((pair >> 8) as u8);
+        alphabet_data.push((pair & 0xFF) as u8);
+    }
+
+    let mut symbols: Vec<u16> = Vec::with_capacity(buffer_in.len());
+    let mut i = 0;
+    while i < buffer_in.len() {
+        if i + 1 < buffer_in.len() {
+            let pair_symbol = map_pair_symbol[(buffer_in[i] as usize) << 8 | buffer_in[i + 1] as usize];
+            if pair_symbol != 0 {
+                symbols.push(pair_symbol);
+                i += 2;
+                continue;
+            }
+        }
+        symbols.push(buffer_in[i] as u16);
+        i += 1;
+    }
+
+    progress!("...alphabet with {} byte pairs, {} bytes in {} symbols", vec_pairs.len(), buffer_in.len(), symbols.len());
+
+    (symbols, 256 + vec_pairs.len(), alphabet_data)
+}
+
+/// Reads the pairs alphabet data. Returns the pairs and the number of bytes
+/// used by the alphabet data.
+pub fn read_pairs_alphabet_data(buffer_in: &[u8]) -> Result<(Vec<[u8; 2]>, usize)> {
+    let num_pairs = match buffer_in {
+        [high, low, ..] => u16::from_be_bytes([*high, *low]) as usize,
+        _ => return Err(Error::BadTable("the pairs of the pairs alphabet are cut short")),
+    };
+    let vec_pairs: Vec<[u8; 2]> = bytes_at(buffer_in, 2, num_pairs * 2)
+        .ok_or(Error::BadTable("the pairs of the pairs alphabet are cut short"))?
+        .chunks_exact(2)
+        .map(|pair| [pair[0], pair[1]])
+        .collect();
+    Ok((vec_pairs, 2 + num_pairs * 2))
+}
+
+/// Converts the symbols back to the bytes, at most max_len bytes. A pair
+/// is 2 bytes, the bytes left to write are checked before each symbol.
+pub fn from_pair_symbols(symbols: &[u16], vec_pairs: &[[u8; 2]], max_len: usize) -> Result<Vec<u8>> {
+    let mut buffer_out = Vec::with_capacity(symbols.len().min(max_len));
+    for &symbol in symbols {
+        if symbol < 256 {
+            if buffer_out.len() == max_len {
+                return Err(Error::PayloadUnderrun("a block decodes to more bytes than it's header"));
+            }
+            buffer_out.push(symbol as u8);
+        } else {
+            let pair = vec_pairs.get(symbol as usize - 256)
+                .ok_or(Error::BadTable("a symbol of the pairs alphabet has no pair"))?;
+            if pair.len() > max_len - buffer_out.len() {
+                return Err(Error::PayloadUnderrun("a block decodes to more bytes than it's header"));
+            }
+            buffer_out.extend_from_slice(pair);
+        }
+    }
+    Ok(buffer_out)
+}
+
+//******************
+//* words alphabet
+//******************
+
+#[derive(PartialEq)]
+enum ByteClass {
+    Word,
+    Space,
+    Other,
+}
+
+fn byte_class(byte: u8) -> ByteClass {
+    if byte.is_ascii_alphanumeric() || byte == b'_' || byte >= 0x80 {
+        ByteClass::Word
+    } else if byte == b' ' {
+        ByteClass::Space
+    } else {
+        ByteClass::Other
+    }
+}
+
+/// Length of the token at the start of the buffer. A word takes the one
+/// space after it, so most words and spaces make a single token.
+fn token_len(buffer: &[u8]) -> usize {
+    let class = byte_class(buffer[0]);
+    if class == ByteClass::Other {
+        return 1;
+    }
+    let mut len = 1;
+    while len < buffer.len() && len < MAX_WORD_LEN && byte_class(buffer[len]) == class {
+        len += 1;
+    }
+    if class == ByteClass::Word && len < buffer.len() && len < MAX_WORD_LEN && buffer[len] == b' ' {
+        len += 1;
+    }
+    len
+}
+
+/// Splits the message in tokens, chooses the dictionary and converts the
+/// message to symbols. The symbols 0 to 255 are the bytes and the symbols
+/// from 256 are the tokens of the dictionary, in sorted order.
+/// Returns the symbols, the alphabet size and the alphabet data, the number
+/// of tokens in 4 bytes and the front coded tokens.
+pub fn to_word_symbols(buffer_in: &[u8]) -> (Vec<u32>, usize, Vec<u8>) {
+    let mut vec_tokens: Vec<&[u8]> = Vec::new();
+    let mut i = 0;
+    while i < buffer_in.len() {
+        let len = token_len(&buffer_in[i..]);
+        vec_tokens.push(&buffer_in[i..i + len]);
+        i += len;
+    }
+
+    let mut map_token_freq: HashMap<&[u8], usize> = HashMap::new();
+    for token in &vec_tokens {
+        *map_token_freq.entry(token).or_insert(0) += 1;
+    }
+
+    // The tokens of one byte are already in the alphabet.
+    let mut vec_words: Vec<(&[u8], usize)> = map_token_freq.iter()
+        .filter(|(token, count)| token.len() > 1 && **count >= MIN_WORD_COUNT)
+        .map(|(token, count)| (*token, *count))
+        .collect();
+    vec_words.sort_by(|(token_a, count_a), (token_b, count_b)| count_b.cmp(count_a).then(token_a.cmp(token_b)));
+    vec_words.truncate(MAX_WORDS);
+    let mut vec_words: Vec<&[u8]> = vec_words.iter().map(|(token, _count)| *token).collect();
+    vec_words.sort();
+
+    let map_word_symbol: HashMap<&[u8], u32> = vec_words.iter()
+        .enumerate()
+        .map(|(index, token)| (*token, 256 + index as u32))
+        .collect();
+
+    let mut symbols: Vec<u32> = Vec::with_capacity(vec_tokens.len());
+    for token in &vec_tokens {
+        match map_word_symbol.get(token) {
+            Some(symbol) => symbols.push(*symbol),
+            None         => symbols.extend(token.iter().map(|&byte| byte as u32)),
+        }
+    }
+
+    // Front coding, each token is written as the length of the prefix that
+    // is equal to the previous token, the length of the rest and the rest.
+    let mut alphabet_data: Vec<u8> = Vec::new();
+    alphabet_data.extend_from_slice(&(vec_words.len() as u32).to_be_bytes());
+    let mut prev_word: &[u8] = &[];
+    for word in &vec_words {
+        let prefix_len = prev_word.iter().zip(word.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        alphabet_data.push(prefix_len as u8);
+        alphabet_data.push((word.len() - prefix_len) as u8);
+        alphabet_data.extend_from_slice(&word[prefix_len..]);
+        prev_word = word;
+    }
+
+    progress!("...alphabet with {} words of {} distinct tokens, {} bytes in {} symbols, dictionary of {} bytes",
+             vec_words.len(), map_token_freq.len(), buffer_in.len(), symbols.len(), alphabet_data.len());
+
+    (symbols, 256 + vec_words.len(), alphabet_data)
+}
+
+/// Reads the words alphabet data, of a block of max_len bytes. Returns the
+/// tokens and the number of bytes used by the alphabet data.
+/// Each word of the dictionary is at least 2 times in the block, so all the
+/// words together are never longer than the block, and a word always has a
+/// rest, it comes after the word before in sorted order. Without these
+/// checks 2 bytes of a crafted table would give a word of 255 bytes.
+pub fn read_words_alphabet_data(buffer_in: &[u8], max_len: usize) -> Result<(Vec<Vec<u8>>, usize)> {
+    let cut_short = "the words of the words alphabet are cut short";
+    let num_words = match bytes_at(buffer_in, 0, 4) {
+        Some(bytes) => u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize,
+        None        => return Err(Error::BadTable(cut_short)),
+    };
+    // Each word takes at least 2 bytes.
+    let mut vec_words: Vec<Vec<u8>> = Vec::with_capacity(num_words.min(buffer_in.len() / 2));
+    let mut pos = 4;
+    let mut words_len = 0;
+    for _ in 0..num_words {
+        let (prefix_len, rest_len) = match bytes_at(buffer_in, pos, 2) {
+            Some(bytes) => (bytes[0] as usize, bytes[1] as usize),
+            None        => return Err(Error::BadTable(cut_short)),
+        };
+        if rest_len == 0 {
+            return Err(Error::BadTable("a word of the words alphabet is only the prefix of the word before"));
+        }
+        words_len += prefix_len + rest_len;
+        if words_len > max_len {
+            return Err(Error::BadTable("the words of the words alphabet are longer than their block"));
+        }
+        let mut word: Vec<u8> = match vec_words.last() {
+            Some(previous) if prefix_len <= previous.len() => previous[..prefix_len].to_vec(),
+            None if prefix_len == 0 => Vec::new(),
+            _ => return Err(Error::BadTable("a word of the words alphabet has a prefix longer than the word before")),
+        };
+        match bytes_at(buffer_in, pos + 2, rest_len) {
+            Some(rest) => word.extend_from_slice(rest),
+            None       => return Err(Error::BadTable(cut_short)),
+        }
+        vec_words.push(word);
+        pos += 2 + rest_len;
+    }
+    Ok((vec_words, pos))
+}
+
+/// Converts the symbols back to the bytes, at most max_len bytes. A word
+/// is up to 255 bytes, the bytes left to write are checked before each one.
+pub fn from_word_symbols(symbols: &[u32], vec_words: &[Vec<u8>], max_len: usize) -> Result<Vec<u8>> {
+    let mut buffer_out = Vec::with_capacity(symbols.len().min(max_len));
+    for &symbol in symbols {
+        if symbol < 256 {
+            if buffer_out.len() == max_len {
+                return Err(Error::PayloadUnderrun("a block decodes to more bytes than it's header"));
+            }
+            buffer_out.push(symbol as u8);
+        } else {
+            let word = vec_words.get(symbol as usize - 256)
+                .ok_or(Error::BadTable("a symbol of the words alphabet has no word"))?;
+            if word.len() > max_len - buffer_out.len() {
+                return Err(Error::PayloadUnderrun("a block decodes to more bytes than it's header"));
+            }
+            buffer_out.extend_from_slice(word);
+        }
+    }
+    Ok(buffer_out)
+}
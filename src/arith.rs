@@ -0,0 +1,244 @@
+// Range coder, an arithmetic coder that outputs whole bytes, as an
+// alternative to the Huffman codes.
+//
+// A Huffman code uses a whole number of bits for each symbol, so a symbol
+// with a probability of 99% still costs one bit. The range coder divides the
+// current range in parts proportional to the frequency of each symbol, a
+// symbol costs -log2(probability) bits, 0.0145 bits for a 99% symbol.
+//
+// The frequencies come from the same counts of the Huffman coding, scaled so
+// that they sum to 2^16. The coded data is:
+//
+//   4 bytes   number of symbols in the table
+//   1 byte    number of bytes of each symbol
+//   entries   the symbol and it's scaled frequency minus one, in 2 bytes
+//   8 bytes   number of symbols of the message
+//   the bytes of the range coder
+//
+// The carry handling is the same one of the range coder of LZMA.
+
+use crate::alphabet::{Symbol, SymbolType};
+use crate::coder::{read_freq_table, read_symbol_counter, scale_counts};
+use crate::error::{bytes_at, Error, Result};
+
+/// The scaled frequencies sum to 2^FREQ_BITS.
+const FREQ_BITS: u32 = 16;
+const FREQ_TOTAL: u32 = 1 << FREQ_BITS;
+
+/// The range is kept bigger than 2^24, each time it gets smaller a byte is
+/// sent to the output.
+const RANGE_TOP: u32 = 1 << 24;
+
+/// Each symbol needs a frequency of at least one, and some precision left
+/// for the frequent symbols.
+pub const MAX_SYMBOLS: usize = (FREQ_TOTAL / 2) as usize;
+
+/// The frequency and the cumulative frequency of each symbol.
+struct FreqTable {
+    vec_symbols: Vec<Symbol>,
+    vec_freq:    Vec<u32>,
+    vec_cum:     Vec<u32>,
+}
+
+impl FreqTable {
+
+    fn from_counts(vec_counts: &[(Symbol, usize)]) -> FreqTable {
+        let vec_freq = scale_counts(vec_counts, FREQ_TOTAL);
+        let vec_symbols = vec_counts.iter().map(|(symbol, _count)| *symbol).collect();
+        FreqTable::from_freq(vec_symbols, vec_freq)
+    }
+
+    fn from_freq(vec_symbols: Vec<Symbol>, vec_freq: Vec<u32>) -> FreqTable {
+        let mut vec_cum: Vec<u32> = Vec::with_capacity(vec_freq.len());
+        let mut cum = 0;
+        for freq in &vec_freq {
+            vec_cum.push(cum);
+            cum += freq;
+        }
+        FreqTable { vec_symbols, vec_freq, vec_cum }
+    }
+}
+
+/// Encodes the message with the range coder. Returns the number of bytes of
+/// the table, or None, and writes nothing, if the alphabet has too many
+/// symbols for the scaled frequencies.
+pub fn encode_the_data<S: SymbolType>(vec_counts: &[(Symbol, usize)], buffer_in: &[S], buffer_out: & mut Vec<u8>) -> Option<usize> {
+    if vec_counts.len() > MAX_SYMBOLS {
+        return None;
+    }
+    // A block too short for a symbol of the alphabet has no counts, the
+    // table still needs one symbol to be valid.
+    let vec_counts = if vec_counts.is_empty() { &[(0, 1)][..] } else { vec_counts };
+    let freq_table = FreqTable::from_counts(vec_counts);
+
+    // Write the table.
+    let table_start = buffer_out.len();
+    let max_symbol = freq_table.vec_symbols.iter().max().copied().unwrap_or(0);
+    let width: usize = if max_symbol > 0xFFFF { 4 } else if max_symbol > 0xFF { 2 } else { 1 };
+    buffer_out.extend_from_slice(& (freq_table.vec_symbols.len() as u32).to_be_bytes());
+    buffer_out.push(width as u8);
+    for (symbol, freq) in freq_table.vec_symbols.iter().zip(freq_table.vec_freq.iter()) {
+        buffer_out.extend_from_slice(& symbol.to_be_bytes()[4 - width..]);
+        buffer_out.extend_from_slice(& ((freq - 1) as u16).to_be_bytes());
+    }
+    let table_len = buffer_out.len() - table_start;
+    buffer_out.extend_from_slice(& (buffer_in.len() as u64).to_be_bytes());
+
+    // Maps each symbol to it's index in the table.
+    let mut map_symbol_index: Vec<usize> = vec![0; max_symbol as usize + 1];
+    for (index, symbol) in freq_table.vec_symbols.iter().enumerate() {
+        map_symbol_index[*symbol as usize] = index;
+    }
+
+    let mut encoder = RangeEncoder::new(buffer_out);
+    for symbol in buffer_in {
+        let index = map_symbol_index[symbol.to_symbol() as usize];
+        encoder.encode(freq_table.vec_cum[index], freq_table.vec_freq[index]);
+    }
+    encoder.flush();
+
+    progress!("\n...symbol_counter or original file byte size {} ", buffer_in.len());
+    Some(table_len)
+}
+
+/// Decodes the message coded with the range coder, of at most max_symbols
+/// symbols.
+pub fn decode_the_data<S: SymbolType>(buffer_in: &[u8], buffer_out: & mut Vec<S>, max_symbols: usize) -> Result<()> {
+    let table_header = bytes_at(buffer_in, 0, 5)
+        .ok_or(Error::BadTable("the frequency table is cut short"))?;
+    let num_entries = u32::from_be_bytes([table_header[0], table_header[1], table_header[2], table_header[3]]) as usize;
+    let width = table_header[4] as usize;
+    let (vec_symbols, vec_freq, mut pos) = read_freq_table(buffer_in, 5, num_entries, width, FREQ_TOTAL)?;
+    let freq_table = FreqTable::from_freq(vec_symbols, vec_freq);
+
+    let symbol_counter = read_symbol_counter(buffer_in, pos, max_symbols)?;
+    pos += 8;
+
+    progress!("\n...symbol_counter or original file byte size {} ", symbol_counter);
+
+    // Maps each value of the scaled frequencies to the index of it's symbol.
+    let mut map_value_index: Vec<u32> = vec![0; FREQ_TOTAL as usize];
+    for (index, (cum, freq)) in freq_table.vec_cum.iter().zip(freq_table.vec_freq.iter()).enumerate() {
+        for value in *cum..*cum + *freq {
+            map_value_index[value as usize] = index as u32;
+        }
+    }
+
+    buffer_out.reserve(symbol_counter);
+    let mut decoder = RangeDecoder::new(& buffer_in[pos..]);
+    for _ in 0..symbol_counter {
+        let value = decoder.get_value();
+        let index = map_value_index[value as usize] as usize;
+        decoder.decode(freq_table.vec_cum[index], freq_table.vec_freq[index]);
+        buffer_out.push(S::from_symbol(freq_table.vec_symbols[index]));
+    }
+    if decoder.pos > decoder.buffer_in.len() {
+        return Err(Error::PayloadUnderrun("the range coder data ends before the last symbol"));
+    }
+    Ok(())
+}
+
+struct RangeEncoder<'a> {
+    low:        u64,
+    range:      u32,
+    cache:      u8,
+    cache_size: u64,
+    buffer_out: &'a mut Vec<u8>,
+}
+
+impl<'a> RangeEncoder<'a> {
+
+    fn new(buffer_out: &'a mut Vec<u8>) -> RangeEncoder<'a> {
+        RangeEncoder {
+            low:        0,
+            range:      0xFFFF_FFFF,
+            cache:      0,
+            cache_size: 1,
+            buffer_out,
+        }
+    }
+
+    fn encode(& mut self, cum: u32, freq: u32) {
+        let r = self.range >> FREQ_BITS;
+        self.low += (r * cum) as u64;
+        self.range = r * freq;
+        while self.range < RANGE_TOP {
+            self.range <<= 8;
+            self.shift_low();
+        }
+    }
+
+    /// Sends the top byte of low to the output. The bytes 0xFF are kept
+    /// in the cache until it's known if a carry will change them.
+    fn shift_low(& mut self) {
+        if (self.low as u32) < 0xFF00_0000 || (self.low >> 32) != 0 {
+            let carry = (self.low >> 32) as u8;
+            let mut temp = self.cache;
+            loop {
+                self.buffer_out.push(temp.wrapping_add(carry));
+                temp = 0xFF;
+                self.cache_size -= 1;
+                if self.cache_size == 0 {
+                    break;
+                }
+            }
+            self.cache = ((self.low >> 24) & 0xFF) as u8;
+        }
+        self.cache_size += 1;
+        self.low = (self.low & 0x00FF_FFFF) << 8;
+    }
+
+    fn flush(& mut self) {
+        for _ in 0..5 {
+            self.shift_low();
+        }
+    }
+}
+
+struct RangeDecoder<'a> {
+    code:      u32,
+    range:     u32,
+    r:         u32,
+    buffer_in: &'a [u8],
+    pos:       usize,
+}
+
+impl<'a> RangeDecoder<'a> {
+
+    fn new(buffer_in: &'a [u8]) -> RangeDecoder<'a> {
+        let mut decoder = RangeDecoder {
+            code:  0,
+            range: 0xFFFF_FFFF,
+            r:     0,
+            buffer_in,
+            pos:   0,
+        };
+        for _ in 0..5 {
+            decoder.code = decoder.code << 8 | decoder.next_byte() as u32;
+        }
+        decoder
+    }
+
+    /// After the end of the data it reads zeros, the caller checks pos.
+    fn next_byte(& mut self) -> u8 {
+        let byte = *self.buffer_in.get(self.pos).unwrap_or(&0);
+        self.pos += 1;
+        byte
+    }
+
+    /// The scaled frequency value where the next symbol is.
+    fn get_value(& mut self) -> u32 {
+        self.r = self.range >> FREQ_BITS;
+        (self.code / self.r).min(FREQ_TOTAL - 1)
+    }
+
+    fn decode(& mut self, cum: u32, freq: u32) {
+        // Corrupt data can take the code out of the range.
+        self.code = self.code.wrapping_sub(self.r * cum);
+        self.range = self.r * freq;
+        while self.range < RANGE_TOP {
+            self.code = self.code << 8 | self.next_byte() as u32;
+            self.range <<= 8;
+        }
+    }
+}
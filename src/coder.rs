@@ -0,0 +1,163 @@
+// The entropy coder used for the symbols of the message.
+//
+//   huffman - The Huffman codes, the default.
+//   arith   - The range coder, an arithmetic coder that gets closer to the
+//             entropy when some symbols are very frequent.
+//   fse     - The tANS coder, close to the range coder in size and with a
+//             table lookup for each symbol when decoding, like Huffman.
+//   dict    - The Huffman codes of a pretrained dictionary, see --table, the
+//             block only has the ID of the dictionary in place of the table.
+//   stored  - The bytes of the block as they are, for the blocks that no
+//             coder makes smaller, like the tiny ones or random bytes.
+//
+// The coder is recorded in the header of each block, so it can be chosen
+// for the whole file or, in auto mode, for each block.
+
+use std::fmt;
+
+use crate::alphabet::Symbol;
+use crate::error::{bytes_at, Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Coder {
+    Huffman,
+    Arith,
+    Fse,
+    Dict,
+    Stored,
+}
+
+/// The coder selected in the command line, auto codes each block with all
+/// the coders and keeps the smallest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoderMode {
+    Fixed(Coder),
+    Auto,
+}
+
+impl CoderMode {
+
+    /// Parses the command line coder, ex: "huffman", "arith", "fse" or "auto".
+    pub fn parse(text: &str) -> Option<CoderMode> {
+        match &text.to_ascii_lowercase()[..] {
+            "huffman" => Some(CoderMode::Fixed(Coder::Huffman)),
+            "arith"   => Some(CoderMode::Fixed(Coder::Arith)),
+            "fse"     => Some(CoderMode::Fixed(Coder::Fse)),
+            "auto"    => Some(CoderMode::Auto),
+            _ => None,
+        }
+    }
+
+    /// The coders to try on each block.
+    pub fn candidates(self) -> Vec<Coder> {
+        match self {
+            CoderMode::Fixed(coder) => vec![coder],
+            CoderMode::Auto         => vec![Coder::Huffman, Coder::Arith, Coder::Fse],
+        }
+    }
+}
+
+impl Coder {
+
+    pub fn to_header_byte(self) -> u8 {
+        match self {
+            Coder::Huffman => 0,
+            Coder::Arith   => 1,
+            Coder::Fse     => 2,
+            Coder::Dict    => 3,
+            Coder::Stored  => 4,
+        }
+    }
+
+    pub fn from_header_byte(id: u8) -> Option<Coder> {
+        match id {
+            0 => Some(Coder::Huffman),
+            1 => Some(Coder::Arith),
+            2 => Some(Coder::Fse),
+            3 => Some(Coder::Dict),
+            4 => Some(Coder::Stored),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Coder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Coder::Huffman => write!(f, "huffman"),
+            Coder::Arith   => write!(f, "arith"),
+            Coder::Fse     => write!(f, "fse"),
+            Coder::Dict    => write!(f, "dict"),
+            Coder::Stored  => write!(f, "stored"),
+        }
+    }
+}
+
+/// Scales the counts of the symbols so that they sum to total, and no symbol
+/// gets a frequency of zero. Used by the range coder and the tANS coder, the
+/// number of symbols must not be more than half of the total.
+pub fn scale_counts(vec_counts: &[(Symbol, usize)], total: u32) -> Vec<u32> {
+    let count_total: u128 = vec_counts.iter().map(|(_symbol, count)| *count as u128).sum();
+    let mut vec_freq: Vec<u32> = vec_counts.iter()
+        .map(|(_symbol, count)| ((*count as u128 * total as u128 / count_total) as u32).max(1))
+        .collect();
+
+    let mut sum: u32 = vec_freq.iter().sum();
+    // The most frequent symbol gets what is missing.
+    let index_max = (0..vec_freq.len()).max_by_key(|&i| vec_freq[i]).unwrap();
+    if sum < total {
+        vec_freq[index_max] += total - sum;
+    }
+    // Or the most frequent symbols give back what is too much.
+    let mut vec_order: Vec<usize> = (0..vec_freq.len()).collect();
+    vec_order.sort_by(|&a, &b| vec_freq[b].cmp(&vec_freq[a]));
+    while sum > total {
+        for &i in &vec_order {
+            if sum > total && vec_freq[i] > 1 {
+                vec_freq[i] -= 1;
+                sum -= 1;
+            }
+        }
+    }
+    vec_freq
+}
+
+/// Reads the table of the range coder or of the tANS coder from pos, the
+/// symbol in width bytes and the frequency minus one in 2 bytes for each of
+/// the num_entries symbols. The frequencies must sum to total.
+/// Returns the symbols, the frequencies and the position after the table.
+pub fn read_freq_table(buffer_in: &[u8], mut pos: usize, num_entries: usize, width: usize, total: u32) -> Result<(Vec<Symbol>, Vec<u32>, usize)> {
+    if num_entries == 0 || num_entries > total as usize || !(1..=4).contains(&width) {
+        return Err(Error::BadTable("the frequency table isn't valid"));
+    }
+    let entries = bytes_at(buffer_in, pos, num_entries * (width + 2))
+        .ok_or(Error::BadTable("the frequency table is cut short"))?;
+    let mut vec_symbols: Vec<Symbol> = Vec::with_capacity(num_entries);
+    let mut vec_freq: Vec<u32> = Vec::with_capacity(num_entries);
+    for entry in entries.chunks_exact(width + 2) {
+        let mut symbol: Symbol = 0;
+        for &byte in & entry[..width] {
+            symbol = symbol << 8 | byte as Symbol;
+        }
+        let freq = u16::from_be_bytes([entry[width], entry[width + 1]]) as u32 + 1;
+        vec_symbols.push(symbol);
+        vec_freq.push(freq);
+    }
+    if vec_freq.iter().map(|&freq| freq as u64).sum::<u64>() != total as u64 {
+        return Err(Error::BadTable("the frequencies of the table don't sum to the total"));
+    }
+    pos += entries.len();
+    Ok((vec_symbols, vec_freq, pos))
+}
+
+/// Reads the 8 bytes of the number of symbols of the message at pos, the
+/// block has at most max_symbols symbols.
+pub fn read_symbol_counter(buffer_in: &[u8], pos: usize, max_symbols: usize) -> Result<usize> {
+    let bytes = bytes_at(buffer_in, pos, 8)
+        .ok_or(Error::PayloadUnderrun("the number of symbols is cut short"))?;
+    let symbol_counter = u64::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]);
+    if symbol_counter > max_symbols as u64 {
+        return Err(Error::PayloadUnderrun("the number of symbols is bigger than the block"));
+    }
+    Ok(symbol_counter as usize)
+}
@@ -0,0 +1,118 @@
+// Generated corpora for the benchmarks, the same bytes in every run and in
+// every machine.
+//
+// The bytes come from a xorshift generator with a fixed seed, so the bench
+// subcommand and the criterion benchmarks measure the same messages without
+// any file in the repository.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Corpus {
+    /// Words of a small vocabulary, the first words much more frequent.
+    Text,
+    /// Uniform random bytes, can't be compressed.
+    Random,
+    /// About 90% of zero bytes, the rest random.
+    Skewed,
+    /// Only zero bytes.
+    Zeros,
+    /// x86 like code with E8 calls to near targets.
+    Executable,
+}
+
+impl Corpus {
+    pub const ALL: [Corpus; 5] = [Corpus::Text, Corpus::Random, Corpus::Skewed, Corpus::Zeros, Corpus::Executable];
+}
+
+impl fmt::Display for Corpus {
+    fn fmt(&self, f: & mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Corpus::Text       => "text",
+            Corpus::Random     => "random",
+            Corpus::Skewed     => "skewed",
+            Corpus::Zeros      => "zeros",
+            Corpus::Executable => "executable",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+static WORDS: [&str; 32] = [
+    "the", "of", "and", "to", "a", "in", "is", "that", "for", "it", "as", "with",
+    "was", "on", "be", "by", "this", "are", "from", "code", "symbol", "tree",
+    "huffman", "message", "byte", "table", "length", "frequency", "decoder",
+    "encoder", "compression", "information",
+];
+
+/// Some common x86 opcodes, without the E8 of the calls.
+static OPCODES: [u8; 16] = [
+    0x48, 0x89, 0x8B, 0x83, 0xC3, 0x55, 0x5D, 0x31, 0xC0, 0x0F, 0x85, 0x74, 0xFF, 0x50, 0x58, 0x90,
+];
+
+/// Generates size bytes of the corpus.
+pub fn generate(kind: Corpus, size: usize) -> Vec<u8> {
+    let mut rng = XorShift::new(0x4A48_4E59);
+    let mut buffer_out: Vec<u8> = Vec::with_capacity(size + 16);
+    match kind {
+        Corpus::Text => {
+            while buffer_out.len() < size {
+                // The minimum of two draws, the first words are more frequent.
+                let index = rng.below(WORDS.len()).min(rng.below(WORDS.len()));
+                buffer_out.extend_from_slice(WORDS[index].as_bytes());
+                buffer_out.push(if rng.below(12) == 0 { b'\n' } else { b' ' });
+            }
+        }
+        Corpus::Random => {
+            while buffer_out.len() < size {
+                buffer_out.extend_from_slice(& rng.next().to_le_bytes());
+            }
+        }
+        Corpus::Skewed => {
+            while buffer_out.len() < size {
+                let value = rng.next();
+                buffer_out.push(if rng.below(10) == 0 { (value >> 32) as u8 } else { 0 });
+            }
+        }
+        Corpus::Zeros => {
+            buffer_out.resize(size, 0);
+        }
+        Corpus::Executable => {
+            while buffer_out.len() < size {
+                if rng.below(8) == 0 {
+                    // A call to a target near the instruction.
+                    let target = rng.below(1 << 16) as i32 - (1 << 15);
+                    buffer_out.push(0xE8);
+                    buffer_out.extend_from_slice(& target.to_le_bytes());
+                } else {
+                    buffer_out.push(OPCODES[rng.below(OPCODES.len())]);
+                }
+            }
+        }
+    }
+    buffer_out.truncate(size);
+    buffer_out
+}
+
+/// The xorshift64 generator of Marsaglia.
+struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+
+    fn new(seed: u64) -> XorShift {
+        XorShift { state: seed }
+    }
+
+    fn next(& mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    fn below(& mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
@@ -0,0 +1,150 @@
+// Pretrained Huffman tables, shared by many small messages.
+//
+// For a message of a few hundred bytes the mapping table can be bigger than
+// the coded data. The table is trained once on a sample corpus and saved in
+// a .jtbl dictionary file, and each block coded with it only stores the ID
+// of the dictionary in place of the table.
+//
+//   bytes 0..4   magic "JTBL"
+//   byte  4      dictionary version
+//   bytes 5..9   ID of the dictionary, the CRC32 of the table, big endian
+//   the mapping table of the bytes alphabet, as in the .johnny blocks
+//
+// The body of a block coded with the dictionary is the 4 bytes of the ID,
+// big endian, followed by the streams. The number of symbols is the number
+// of bytes of the block header. The table is read once, when the dictionary
+// is made, and the codes are kept for all the blocks.
+//
+// The training smooths the counts, the bytes missing from the sample get the
+// smallest count, so every byte has a code and the messages with a byte that
+// the sample didn't have are still coded with the dictionary. The tables of
+// older dictionaries may not have all the bytes, a block with a byte without
+// a code is then coded with it's own table.
+
+use crate::alphabet::Symbol;
+use crate::error::{Error, Result};
+use crate::histogram;
+use crate::huffman::{MappingTable, TABLE_HEADER_SIZE};
+use crate::streams;
+
+pub const DICTIONARY_MAGIC: &[u8; 4] = b"JTBL";
+pub const DICTIONARY_VERSION: u8 = 2;
+const DICTIONARY_HEADER_SIZE: usize = 9;
+
+/// The count of the bytes that aren't in the sample.
+const MIN_COUNT: usize = 1;
+
+/// Bytes of the ID in the body of each block.
+pub const ID_SIZE: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct Dictionary {
+    pub id: u32,
+    table:  Vec<u8>,
+    /// The code of each byte of the table, as a number and it's length.
+    codes:  Vec<(Symbol, u64, u32)>,
+}
+
+impl Dictionary {
+
+    /// Builds the Huffman table of the bytes of the sample. The bytes that
+    /// aren't in the sample get the count of one, so they have a long code
+    /// and any message can be coded with the table.
+    pub fn train(sample: &[u8]) -> Dictionary {
+        let mut counts = histogram::count_bytes(sample);
+        let num_missing = counts.iter().filter(|&&count| count == 0).count();
+        progress!("...{} of the 256 bytes aren't in the sample, smoothed to the count of one", num_missing);
+        for count in counts.iter_mut() {
+            *count = (*count).max(MIN_COUNT);
+        }
+
+        let mut map_table = MappingTable::new();
+        map_table.set_symbol_counts(& counts);
+        map_table.generate_huffman_code();
+        let mut table: Vec<u8> = Vec::new();
+        map_table.write_mapping_table_to_byte_buffer(& mut table);
+        let id = crc32fast::hash(& table);
+        Dictionary { id, table, codes: map_table.code_values() }
+    }
+
+    /// The bytes of the .jtbl file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buffer_out: Vec<u8> = Vec::with_capacity(DICTIONARY_HEADER_SIZE + self.table.len());
+        buffer_out.extend_from_slice(DICTIONARY_MAGIC);
+        buffer_out.push(DICTIONARY_VERSION);
+        buffer_out.extend_from_slice(& self.id.to_be_bytes());
+        buffer_out.extend_from_slice(& self.table);
+        buffer_out
+    }
+
+    /// Reads the .jtbl file, the table must match the ID.
+    pub fn from_bytes(buffer_in: &[u8]) -> Result<Dictionary> {
+        if buffer_in.len() < DICTIONARY_HEADER_SIZE + TABLE_HEADER_SIZE
+            || &buffer_in[0..4] != DICTIONARY_MAGIC
+            || buffer_in[4] != DICTIONARY_VERSION {
+            return Err(Error::BadTable("not a .jtbl dictionary file"));
+        }
+        let id = u32::from_be_bytes([buffer_in[5], buffer_in[6], buffer_in[7], buffer_in[8]]);
+        let table = buffer_in[DICTIONARY_HEADER_SIZE..].to_vec();
+        if crc32fast::hash(& table) != id {
+            return Err(Error::BadTable("the table of the dictionary doesn't match it's ID"));
+        }
+        let mut map_table = MappingTable::new();
+        let table_end = map_table.read_mapping_table_from_byte_buffer(& table)?;
+        if table_end != table.len() {
+            return Err(Error::BadTable("the dictionary file has bytes after the table"));
+        }
+        // The training always gives codes to all the bytes, a single byte
+        // would have the empty code of a run.
+        if map_table.code_lengths().len() < 2 {
+            return Err(Error::BadTable("the dictionary has less than 2 bytes with a code"));
+        }
+        Ok(Dictionary { id, table, codes: map_table.code_values() })
+    }
+
+    /// Number of different bytes with a code.
+    pub fn num_symbols(&self) -> usize {
+        self.codes.len()
+    }
+
+    /// Codes the block with the table of the dictionary, returns None if the
+    /// block has a byte without a code.
+    pub(crate) fn encode_block(&self, block: &[u8], num_streams: usize) -> Option<Vec<u8>> {
+        let counts = histogram::count_bytes(block);
+        let mut has_code = [false; 256];
+        for (symbol, _value, _len) in & self.codes {
+            has_code[*symbol as usize] = true;
+        }
+        if (0..256).any(|byte| counts[byte] > 0 && !has_code[byte]) {
+            return None;
+        }
+        let mut buffer_body: Vec<u8> = Vec::new();
+        buffer_body.extend_from_slice(& self.id.to_be_bytes());
+        streams::encode_streams(& self.codes, block, num_streams, & mut buffer_body);
+        Some(buffer_body)
+    }
+
+    /// Decodes the body of a block coded with the dictionary, to the
+    /// num_symbols bytes of the block header.
+    pub(crate) fn decode_block(&self, buffer_body: &[u8], num_symbols: usize) -> Result<Vec<u8>> {
+        let id = read_id(buffer_body)?;
+        if id != self.id {
+            return Err(Error::Dictionary { needed: id, given: Some(self.id) });
+        }
+        // Each code has at least one bit.
+        if num_symbols / 8 > buffer_body.len() - ID_SIZE {
+            return Err(Error::PayloadUnderrun("the Huffman streams are shorter than the number of symbols"));
+        }
+        let mut buffer_out: Vec<u8> = Vec::new();
+        streams::decode_streams(& self.codes, & buffer_body[ID_SIZE..], num_symbols, & mut buffer_out)?;
+        Ok(buffer_out)
+    }
+}
+
+/// The ID of the dictionary at the start of the body of a block.
+pub(crate) fn read_id(buffer_body: &[u8]) -> Result<u32> {
+    match buffer_body {
+        [b0, b1, b2, b3, ..] => Ok(u32::from_be_bytes([*b0, *b1, *b2, *b3])),
+        _ => Err(Error::PayloadUnderrun("the ID of the dictionary is cut short")),
+    }
+}
@@ -0,0 +1,226 @@
+// The authenticated encryption of the .johnny files.
+//
+// The .johnny file is coded as always, and then all of it, the header, the
+// tables and the coded data, is encrypted with ChaCha20-Poly1305 in an
+// envelope with it's own header. The key is a raw key of 32 bytes, or it's
+// derived from a password with Argon2id, with the parameters and the salt
+// of the envelope header.
+//
+//   bytes 0..4   magic "JHNX"
+//   byte  4      envelope version
+//   byte  5      key derivation, 0 raw key, 1 Argon2id
+//   bytes 6..10  Argon2id memory in KiB, big endian
+//   bytes 10..14 Argon2id iterations, big endian
+//   byte  14     Argon2id lanes
+//   bytes 15..31 salt of the key derivation
+//   bytes 31..43 nonce of ChaCha20-Poly1305
+//
+// After the header come the ciphertext and the 16 bytes of the Poly1305 tag.
+// The envelope header is the associated data of the cipher, so a changed
+// parameter, salt or nonce fails the authentication like a changed byte of
+// the ciphertext. The tag is checked before any byte is decrypted, the
+// decoder never sees a plaintext that isn't authenticated.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+use crate::error::{Error, Result};
+use crate::limits::Limits;
+
+pub const MAGIC: &[u8; 4] = b"JHNX";
+pub const ENVELOPE_VERSION: u8 = 1;
+pub const ENVELOPE_HEADER_SIZE: usize = 43;
+pub const KEY_SIZE: usize = 32;
+pub const TAG_SIZE: usize = 16;
+
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+
+const KDF_RAW_KEY: u8 = 0;
+const KDF_ARGON2ID: u8 = 1;
+
+/// The Argon2id parameters of a file are checked before the key is derived,
+/// a crafted header can't ask for more memory or time than these, 256 MiB
+/// and 10 iterations. The memory is also checked with the limits.
+const MAX_MEMORY_KIB: u32 = 256 * 1024;
+const MAX_ITERATIONS: u32 = 10;
+const MAX_LANES: u8 = 16;
+
+/// A password, derived to a key with Argon2id, or a raw key.
+pub enum Secret {
+    Password(Vec<u8>),
+    Key([u8; KEY_SIZE]),
+}
+
+impl Secret {
+
+    /// The key of a key file, 32 raw bytes or 64 hexadecimal digits.
+    pub fn key_from_bytes(bytes: &[u8]) -> Option<Secret> {
+        parse_key(bytes).map(Secret::Key)
+    }
+}
+
+/// A key of 32 bytes, raw or in 64 hexadecimal digits, the format of the
+/// key files of the encryption and of the signatures.
+pub fn parse_key(bytes: &[u8]) -> Option<[u8; KEY_SIZE]> {
+    let mut key = [0u8; KEY_SIZE];
+    if bytes.len() == KEY_SIZE {
+        key.copy_from_slice(bytes);
+        return Some(key);
+    }
+    let hex = std::str::from_utf8(bytes).ok()?.trim();
+    if hex.len() != 2 * KEY_SIZE || !hex.is_ascii() {
+        return None;
+    }
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(& hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(key)
+}
+
+/// The 64 hexadecimal digits of a key, with a line break, for a key file.
+pub fn key_to_hex(key: &[u8; KEY_SIZE]) -> String {
+    let mut hex: String = key.iter().map(|byte| format!("{:02x}", byte)).collect();
+    hex.push('\n');
+    hex
+}
+
+// The secret is never printed, not even in the debug output of the options.
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Secret::Password(_) => write!(f, "Secret::Password(..)"),
+            Secret::Key(_)      => write!(f, "Secret::Key(..)"),
+        }
+    }
+}
+
+/// The cost of the Argon2id key derivation of a password.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub lanes:      u8,
+}
+
+impl Default for KdfParams {
+    /// 64 MiB and 3 iterations, a fraction of a second.
+    fn default() -> KdfParams {
+        KdfParams { memory_kib: 64 * 1024, iterations: 3, lanes: 1 }
+    }
+}
+
+impl KdfParams {
+
+    fn check(&self) -> Result<Params> {
+        if self.memory_kib > MAX_MEMORY_KIB || self.iterations > MAX_ITERATIONS || self.lanes > MAX_LANES {
+            return Err(Error::BadHeader("the key derivation parameters are too big"));
+        }
+        Params::new(self.memory_kib, self.iterations, self.lanes as u32, Some(KEY_SIZE))
+            .map_err(|_| Error::BadHeader("the key derivation parameters are out of range"))
+    }
+}
+
+/// True if the buffer starts with the magic of an encrypted .johnny file.
+pub fn is_encrypted(buffer_in: &[u8]) -> bool {
+    buffer_in.len() >= MAGIC.len() && &buffer_in[0..4] == MAGIC
+}
+
+/// Encrypts the .johnny file, a password is derived with the default
+/// parameters.
+pub fn encrypt(buffer_johnny: &[u8], secret: & Secret) -> Result<Vec<u8>> {
+    encrypt_with_params(buffer_johnny, secret, & KdfParams::default())
+}
+
+pub fn encrypt_with_params(buffer_johnny: &[u8], secret: & Secret, kdf_params: & KdfParams) -> Result<Vec<u8>> {
+    // 1. A new salt and a new nonce for each file, the same password never
+    //    gives the same key and nonce twice.
+    let mut salt = [0u8; SALT_SIZE];
+    OsRng.fill_bytes(& mut salt);
+    let nonce = ChaCha20Poly1305::generate_nonce(& mut OsRng);
+
+    // 2. The envelope header, the parameters of a raw key are zero.
+    let (kdf_id, kdf_params) = match secret {
+        Secret::Password(_) => (KDF_ARGON2ID, *kdf_params),
+        Secret::Key(_)      => (KDF_RAW_KEY, KdfParams { memory_kib: 0, iterations: 0, lanes: 0 }),
+    };
+    let mut buffer_out: Vec<u8> = Vec::with_capacity(ENVELOPE_HEADER_SIZE + buffer_johnny.len() + TAG_SIZE);
+    buffer_out.extend_from_slice(MAGIC);
+    buffer_out.push(ENVELOPE_VERSION);
+    buffer_out.push(kdf_id);
+    buffer_out.extend_from_slice(& kdf_params.memory_kib.to_be_bytes());
+    buffer_out.extend_from_slice(& kdf_params.iterations.to_be_bytes());
+    buffer_out.push(kdf_params.lanes);
+    buffer_out.extend_from_slice(& salt);
+    buffer_out.extend_from_slice(& nonce);
+
+    // 3. The ciphertext and the tag, of the .johnny file and of the header.
+    let key = derive_key(secret, & kdf_params, & salt)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(& key));
+    let ciphertext = cipher.encrypt(& nonce, Payload { msg: buffer_johnny, aad: & buffer_out })
+        .map_err(|_| Error::Authentication("the message is too big to encrypt"))?;
+    buffer_out.extend_from_slice(& ciphertext);
+    Ok(buffer_out)
+}
+
+/// Decrypts an encrypted .johnny file, it returns the .johnny file only if
+/// the secret is the one of the file and nothing was changed.
+pub fn decrypt(buffer_in: &[u8], secret: & Secret) -> Result<Vec<u8>> {
+    decrypt_with_limits(buffer_in, secret, & Limits::NONE)
+}
+
+/// Decrypts an encrypted .johnny file, the memory of the key derivation, of
+/// the untrusted header, is checked with the limits before it's allocated.
+pub fn decrypt_with_limits(buffer_in: &[u8], secret: & Secret, limits: & Limits) -> Result<Vec<u8>> {
+    if !is_encrypted(buffer_in) {
+        return Err(Error::Authentication("the file isn't encrypted, it can't be authenticated"));
+    }
+    if buffer_in.len() < 5 {
+        return Err(Error::TruncatedHeader("the encryption header is cut short"));
+    }
+    if buffer_in[4] != ENVELOPE_VERSION {
+        return Err(Error::UnsupportedVersion(buffer_in[4]));
+    }
+    if buffer_in.len() < ENVELOPE_HEADER_SIZE + TAG_SIZE {
+        return Err(Error::TruncatedHeader("the encrypted file is cut short"));
+    }
+    let header = & buffer_in[..ENVELOPE_HEADER_SIZE];
+    let kdf_params = KdfParams {
+        memory_kib: u32::from_be_bytes([header[6], header[7], header[8], header[9]]),
+        iterations: u32::from_be_bytes([header[10], header[11], header[12], header[13]]),
+        lanes:      header[14],
+    };
+    let salt = & header[15..15 + SALT_SIZE];
+    let nonce = Nonce::from_slice(& header[31..31 + NONCE_SIZE]);
+
+    // The secret must be of the kind of the file, a password isn't a key.
+    match (header[5], secret) {
+        (KDF_ARGON2ID, Secret::Password(_)) | (KDF_RAW_KEY, Secret::Key(_)) => {}
+        (KDF_ARGON2ID, Secret::Key(_)) => return Err(Error::Encrypted("the file was encrypted with a password, use --password-file")),
+        (KDF_RAW_KEY, Secret::Password(_)) => return Err(Error::Encrypted("the file was encrypted with a key, use --key-file")),
+        _ => return Err(Error::BadHeader("unknown key derivation")),
+    }
+    if header[5] == KDF_ARGON2ID {
+        limits.check_memory(kdf_params.memory_kib as u64 * 1024)?;
+    }
+
+    let key = derive_key(secret, & kdf_params, salt)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(& key));
+    cipher.decrypt(nonce, Payload { msg: & buffer_in[ENVELOPE_HEADER_SIZE..], aad: header })
+        .map_err(|_| Error::Authentication("wrong password or key, or the file was changed"))
+}
+
+fn derive_key(secret: & Secret, kdf_params: & KdfParams, salt: &[u8]) -> Result<[u8; KEY_SIZE]> {
+    match secret {
+        Secret::Key(key) => Ok(*key),
+        Secret::Password(password) => {
+            let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, kdf_params.check()?);
+            let mut key = [0u8; KEY_SIZE];
+            argon2.hash_password_into(password, salt, & mut key)
+                .map_err(|_| Error::BadHeader("the key derivation parameters are out of range"))?;
+            Ok(key)
+        }
+    }
+}
@@ -0,0 +1,123 @@
+// The errors of the command line, of the files and of the decompression.
+//
+// Every stage of the decompression returns a Result, a corrupt or cut short
+// .johnny file gives one of these errors in place of a panic. The command
+// line prints the message and exits with the code of the error, each kind
+// of error has it's own code.
+
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Invalid or missing command line arguments.
+    Usage(String),
+    /// A file can't be read or written.
+    Io { filename: String, source: io::Error },
+    /// The file doesn't start with the magic of the .johnny files.
+    NotJohnny,
+    /// The .johnny header or a block header is cut short.
+    TruncatedHeader(&'static str),
+    /// A header has an unknown filter, alphabet or coder.
+    BadHeader(&'static str),
+    /// The .johnny file was written by another version of the format.
+    UnsupportedVersion(u8),
+    /// The table of a coder, of an alphabet or of a dictionary isn't valid.
+    BadTable(&'static str),
+    /// The coded data ends before all the symbols are decoded, or decodes to
+    /// another number of bytes.
+    PayloadUnderrun(&'static str),
+    /// The decoded message doesn't have the checksum of the original one.
+    ChecksumMismatch { expected: u32, actual: u32 },
+    /// The blocks were coded with a dictionary, and it wasn't given or it's
+    /// another one.
+    Dictionary { needed: u32, given: Option<u32> },
+    /// The message would be bigger than a limit of the decompression.
+    LimitExceeded { limit: &'static str, value: u64, max: u64 },
+    /// The input file changed it's size or was modified while it was read.
+    FileChanged(String),
+    /// The file is encrypted, and the password or the key wasn't given, or
+    /// it's the other kind of secret.
+    Encrypted(&'static str),
+    /// The password or the key is wrong, or the encrypted file was changed.
+    Authentication(&'static str),
+    /// The file isn't signed, or the signature isn't of the verifying key.
+    Signature(&'static str),
+    /// The file has no recovery record, or it's record is damaged.
+    Recovery(&'static str),
+    /// More shards are damaged than the recovery record has parity shards.
+    Unrepairable { damaged: usize, parity: usize },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+
+    /// The exit code of the command line, zero is success.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Usage(_)                => 2,
+            Error::Io { .. }               => 3,
+            Error::NotJohnny               => 4,
+            Error::TruncatedHeader(_)      => 5,
+            Error::BadHeader(_)            => 6,
+            Error::UnsupportedVersion(_)   => 7,
+            Error::BadTable(_)             => 8,
+            Error::PayloadUnderrun(_)      => 9,
+            Error::ChecksumMismatch { .. } => 10,
+            Error::Dictionary { .. }       => 11,
+            Error::LimitExceeded { .. }    => 12,
+            Error::FileChanged(_)          => 13,
+            Error::Encrypted(_)            => 14,
+            Error::Authentication(_)       => 15,
+            Error::Signature(_)            => 16,
+            Error::Recovery(_)             => 17,
+            Error::Unrepairable { .. }     => 18,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Usage(message)           => write!(f, "{}", message),
+            Error::Io { filename, source }  => write!(f, "can't access the file '{}': {}", filename, source),
+            Error::NotJohnny                => write!(f, "not a .johnny file"),
+            Error::TruncatedHeader(message) => write!(f, "truncated header: {}", message),
+            Error::BadHeader(message)       => write!(f, "bad header: {}", message),
+            Error::UnsupportedVersion(version) => write!(f, "unsupported .johnny format version {}", version),
+            Error::BadTable(message)        => write!(f, "bad table: {}", message),
+            Error::PayloadUnderrun(message) => write!(f, "payload underrun: {}", message),
+            Error::ChecksumMismatch { expected, actual } =>
+                write!(f, "checksum mismatch: the file has {:08x} and the decoded message {:08x}", expected, actual),
+            Error::Dictionary { needed, given: None } =>
+                write!(f, "the file was coded with the dictionary {:08x}, use --table dict.jtbl", needed),
+            Error::Dictionary { needed, given: Some(given) } =>
+                write!(f, "the file was coded with the dictionary {:08x}, not with {:08x}", needed, given),
+            Error::LimitExceeded { limit, value, max } =>
+                write!(f, "the {} of {} is over the limit of {}", limit, value, max),
+            Error::FileChanged(filename) =>
+                write!(f, "the file '{}' changed while it was read, the output would be wrong", filename),
+            Error::Encrypted(message)       => write!(f, "encrypted file: {}", message),
+            Error::Authentication(message)  => write!(f, "authentication failed: {}", message),
+            Error::Signature(message)       => write!(f, "bad signature: {}", message),
+            Error::Recovery(message)        => write!(f, "recovery record: {}", message),
+            Error::Unrepairable { damaged, parity } =>
+                write!(f, "{} shards are damaged and the recovery record can only rebuild {}", damaged, parity),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// The len bytes at start, or None if the buffer is cut short.
+pub(crate) fn bytes_at(buffer_in: &[u8], start: usize, len: usize) -> Option<&[u8]> {
+    buffer_in.get(start..start.checked_add(len)?)
+}
@@ -0,0 +1,368 @@
+// Reversible pre-filters applied to the message bytes before the Huffman coding.
+//
+// Huffman coding on bytes only looks at the frequency of each byte value, so
+// raw little-endian samples (i16, i32, f32 ...) of slow changing signals barely
+// compress, the high and low bytes of each sample are all mixed together.
+// The filters bellow transform the message into something with a much more
+// skewed byte distribution, and are undone after the decoding.
+//
+//   delta:N   - Each byte is replaced by the difference to the byte N positions
+//               before. delta:1 is the plain byte delta, delta:2 and delta:4
+//               work on the corresponding bytes of consecutive i16 or i32 samples.
+//   shuffle:N - Split the byte planes, like Blosc. For elements of N bytes all
+//               the first bytes come first, then all the second bytes, ...
+//               The bytes that don't fill a complete element are kept at the end.
+//               The byte frequencies don't change, only the order, so the gain
+//               comes from keeping each plane in it's own region of the message.
+//   x86       - Branch filter (BCJ) for x86 and x86-64 executables. The relative
+//               addresses of the call and jump instructions are converted to
+//               absolute addresses, several calls to the same function then have
+//               the same bytes.
+//   arm64     - The same branch filter for the BL instructions of ARM64.
+//
+// The filter used is recorded in the .johnny header.
+
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::histogram;
+
+/// Size of each chunk taken from the message, to test the filters in auto mode.
+const SAMPLE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Maximum number of chunks taken from the message in auto mode.
+const SAMPLE_MAX_CHUNKS: usize = 16;
+
+/// In auto mode a filter is only chosen if it's estimate is at least 1% better,
+/// for random data the filters would win by noise.
+const MIN_GAIN_FACTOR: f64 = 0.99;
+
+/// Bytes of the quick estimate, to skip the test of the filters when the
+/// message looks incompressible, like random or already compressed data.
+const QUICK_SAMPLE_SIZE: usize = 256 * 1024;
+const INCOMPRESSIBLE_BITS_PER_BYTE: f64 = 7.99;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    None,
+    Delta(u8),
+    Shuffle(u8),
+    X86,
+    Arm64,
+}
+
+/// The filter selected in the command line, auto tries some candidate filters
+/// on a sample of the message and keeps the best one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterMode {
+    Fixed(Filter),
+    Auto,
+}
+
+impl FilterMode {
+    /// Parses the command line filter, ex: "none", "delta", "delta:2", "shuffle:4" or "auto".
+    pub fn parse(text: &str) -> Option<FilterMode> {
+        let text = text.to_ascii_lowercase();
+        let (name, param) = match text.find(':') {
+            Some(index) => (&text[..index], Some(&text[index + 1..])),
+            None        => (&text[..], None),
+        };
+        let param: Option<u8> = match param {
+            Some(p) => match p.parse::<u8>() {
+                           Ok(n) if n >= 1 => Some(n),
+                           _               => return None,
+                       },
+            None    => None,
+        };
+        match (name, param) {
+            ("none", None)       => Some(FilterMode::Fixed(Filter::None)),
+            ("auto", None)       => Some(FilterMode::Auto),
+            ("delta", None)      => Some(FilterMode::Fixed(Filter::Delta(1))),
+            ("delta", Some(n))   => Some(FilterMode::Fixed(Filter::Delta(n))),
+            ("shuffle", Some(n)) => Some(FilterMode::Fixed(Filter::Shuffle(n))),
+            ("x86", None)        => Some(FilterMode::Fixed(Filter::X86)),
+            ("arm64", None)      => Some(FilterMode::Fixed(Filter::Arm64)),
+            _ => None,
+        }
+    }
+}
+
+impl Filter {
+
+    /// The filter identifier and parameter that are written in the header.
+    pub fn to_header_bytes(self) -> (u8, u8) {
+        match self {
+            Filter::None       => (0, 0),
+            Filter::Delta(n)   => (1, n),
+            Filter::Shuffle(n) => (2, n),
+            Filter::X86        => (3, 0),
+            Filter::Arm64      => (4, 0),
+        }
+    }
+
+    /// Reads back the filter from the header bytes.
+    pub fn from_header_bytes(id: u8, param: u8) -> Option<Filter> {
+        match (id, param) {
+            (0, _)          => Some(Filter::None),
+            (1, n) if n > 0 => Some(Filter::Delta(n)),
+            (2, n) if n > 0 => Some(Filter::Shuffle(n)),
+            (3, _)          => Some(Filter::X86),
+            (4, _)          => Some(Filter::Arm64),
+            _ => None,
+        }
+    }
+
+    /// Applies the filter to the message, before the encoding.
+    /// Without filter the buffer isn't copied.
+    pub fn encode(self, buffer_in: &[u8]) -> Cow<'_, [u8]> {
+        match self {
+            Filter::None       => Cow::Borrowed(buffer_in),
+            Filter::Delta(n)   => Cow::Owned(delta_encode(buffer_in, n as usize)),
+            Filter::Shuffle(n) => Cow::Owned(shuffle(buffer_in, n as usize)),
+            Filter::X86        => Cow::Owned(x86_convert(buffer_in, true)),
+            Filter::Arm64      => Cow::Owned(arm64_convert(buffer_in, true)),
+        }
+    }
+
+    /// Undoes the filter, after the decoding.
+    pub fn decode(self, buffer_in: Vec<u8>) -> Vec<u8> {
+        match self {
+            Filter::None       => buffer_in,
+            Filter::Delta(n)   => delta_decode(& buffer_in, n as usize),
+            Filter::Shuffle(n) => unshuffle(& buffer_in, n as usize),
+            Filter::X86        => x86_convert(& buffer_in, false),
+            Filter::Arm64      => arm64_convert(& buffer_in, false),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Filter::None       => write!(f, "none"),
+            Filter::Delta(n)   => write!(f, "delta:{}", n),
+            Filter::Shuffle(n) => write!(f, "shuffle:{}", n),
+            Filter::X86        => write!(f, "x86"),
+            Filter::Arm64      => write!(f, "arm64"),
+        }
+    }
+}
+
+fn delta_encode(buffer_in: &[u8], stride: usize) -> Vec<u8> {
+    let mut buffer_out = buffer_in.to_vec();
+    for i in stride..buffer_in.len() {
+        buffer_out[i] = buffer_in[i].wrapping_sub(buffer_in[i - stride]);
+    }
+    buffer_out
+}
+
+fn delta_decode(buffer_in: &[u8], stride: usize) -> Vec<u8> {
+    let mut buffer_out = buffer_in.to_vec();
+    for i in stride..buffer_out.len() {
+        buffer_out[i] = buffer_out[i].wrapping_add(buffer_out[i - stride]);
+    }
+    buffer_out
+}
+
+fn shuffle(buffer_in: &[u8], elem_size: usize) -> Vec<u8> {
+    let num_elems = buffer_in.len() / elem_size;
+    let mut buffer_out = Vec::with_capacity(buffer_in.len());
+    for plane in 0..elem_size {
+        for elem in 0..num_elems {
+            buffer_out.push(buffer_in[elem * elem_size + plane]);
+        }
+    }
+    // The bytes of the last incomplete element.
+    buffer_out.extend_from_slice(&buffer_in[num_elems * elem_size..]);
+    buffer_out
+}
+
+fn unshuffle(buffer_in: &[u8], elem_size: usize) -> Vec<u8> {
+    let num_elems = buffer_in.len() / elem_size;
+    let mut buffer_out = vec![0; buffer_in.len()];
+    for plane in 0..elem_size {
+        for elem in 0..num_elems {
+            buffer_out[elem * elem_size + plane] = buffer_in[plane * num_elems + elem];
+        }
+    }
+    let tail_start = num_elems * elem_size;
+    buffer_out[tail_start..].copy_from_slice(&buffer_in[tail_start..]);
+    buffer_out
+}
+
+/// x86 branch filter. The E8 (CALL rel32) and E9 (JMP rel32) instructions are
+/// followed by a 32 bit little-endian displacement, relative to the end of the
+/// instruction. Only the displacements with the most significant byte 0x00 or
+/// 0xFF are converted, the ones that are in the range of +- 16 MB, because the
+/// others are most of the times not instructions at all.
+/// The conversion is made modulo 2^25 and sign extended, so the converted
+/// value also has the most significant byte 0x00 or 0xFF. After an E8 or E9
+/// the next 4 bytes are always skipped, converted or not, so the decoder
+/// always looks at the same bytes as the encoder when deciding to convert.
+fn x86_convert(buffer_in: &[u8], encoding: bool) -> Vec<u8> {
+    let mut buffer_out = buffer_in.to_vec();
+    let mut i = 0;
+    while i + 5 <= buffer_out.len() {
+        let opcode = buffer_out[i];
+        if opcode != 0xE8 && opcode != 0xE9 {
+            i += 1;
+            continue;
+        }
+        let msb = buffer_out[i + 4];
+        if msb == 0x00 || msb == 0xFF {
+            let mut bytes = [0_u8; 4];
+            bytes.copy_from_slice(&buffer_out[i + 1..i + 5]);
+            let value = u32::from_le_bytes(bytes);
+            let position = (i as u32).wrapping_add(5);
+            let converted = if encoding {
+                value.wrapping_add(position)
+            } else {
+                value.wrapping_sub(position)
+            };
+            // Sign extend the lower 25 bits.
+            let converted = ((converted << 7) as i32 >> 7) as u32;
+            buffer_out[i + 1..i + 5].copy_from_slice(&converted.to_le_bytes());
+        }
+        i += 5;
+    }
+    buffer_out
+}
+
+/// ARM64 branch filter. The BL instruction is a 32 bit little-endian word with
+/// the upper 6 bits 100101 and a 26 bit displacement in words, relative to the
+/// address of the instruction. Only the words aligned to 4 bytes are looked at.
+fn arm64_convert(buffer_in: &[u8], encoding: bool) -> Vec<u8> {
+    let mut buffer_out = buffer_in.to_vec();
+    let mut i = 0;
+    while i + 4 <= buffer_out.len() {
+        let mut bytes = [0_u8; 4];
+        bytes.copy_from_slice(&buffer_out[i..i + 4]);
+        let instruction = u32::from_le_bytes(bytes);
+        if instruction & 0xFC00_0000 == 0x9400_0000 {
+            let value = instruction & 0x03FF_FFFF;
+            let position = (i as u32) >> 2;
+            let converted = if encoding {
+                value.wrapping_add(position)
+            } else {
+                value.wrapping_sub(position)
+            };
+            let instruction = 0x9400_0000 | (converted & 0x03FF_FFFF);
+            buffer_out[i..i + 4].copy_from_slice(&instruction.to_le_bytes());
+        }
+        i += 4;
+    }
+    buffer_out
+}
+
+/// Looks at the header of ELF, PE (Windows) and Mach-O executables to find out
+/// if the message is code for x86 or ARM64.
+fn detect_executable(buffer_in: &[u8]) -> Option<Filter> {
+    let read_u16 = |pos: usize| -> Option<u16> {
+        buffer_in.get(pos..pos + 2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+    };
+    let read_u32 = |pos: usize| -> Option<u32> {
+        buffer_in.get(pos..pos + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+
+    if buffer_in.starts_with(b"\x7FELF") {
+        // e_machine, little-endian executables only.
+        return match read_u16(18)? {
+            0x03 | 0x3E => Some(Filter::X86),
+            0xB7        => Some(Filter::Arm64),
+            _           => None,
+        };
+    }
+    if buffer_in.starts_with(b"MZ") {
+        // The PE header offset is at 0x3C, the machine comes after "PE\0\0".
+        let pe_offset = read_u32(0x3C)? as usize;
+        if buffer_in.get(pe_offset..pe_offset + 4)? != b"PE\0\0" {
+            return None;
+        }
+        return match read_u16(pe_offset + 4)? {
+            0x014C | 0x8664 => Some(Filter::X86),
+            0xAA64          => Some(Filter::Arm64),
+            _               => None,
+        };
+    }
+    // Mach-O 64 bits, little-endian.
+    if read_u32(0)? == 0xFEED_FACF {
+        return match read_u32(4)? {
+            0x0100_0007 => Some(Filter::X86),
+            0x0100_000C => Some(Filter::Arm64),
+            _           => None,
+        };
+    }
+    None
+}
+
+/// Auto mode, tries each candidate filter on a sample of the message and
+/// chooses the one that gives the lowest order zero entropy, that is very close
+/// to the size that the Huffman coding will reach. The shuffle filters don't
+/// change the order zero entropy, so they are not candidates. The branch
+/// filters are only tried for executables.
+pub fn choose_filter(buffer_in: &[u8]) -> Filter {
+    let mut candidates = vec![
+        Filter::None,
+        Filter::Delta(1),
+        Filter::Delta(2),
+        Filter::Delta(4),
+        Filter::Delta(8),
+    ];
+    if let Some(filter) = detect_executable(buffer_in) {
+        progress!("...detected executable, trying the {} branch filter", filter);
+        candidates.insert(1, filter);
+    } else {
+        let counts = histogram::count_bytes_sampled(buffer_in, QUICK_SAMPLE_SIZE);
+        let total: usize = counts.iter().sum();
+        if counts_entropy_bits(& counts) / total as f64 > INCOMPRESSIBLE_BITS_PER_BYTE {
+            progress!("...the message looks incompressible, not testing the filters");
+            return Filter::None;
+        }
+    }
+
+    let sample = get_sample(buffer_in);
+
+    let mut best_filter = Filter::None;
+    let mut best_bits = f64::MAX;
+    for filter in candidates.iter() {
+        let bits = entropy_bits(& filter.encode(& sample));
+        progress!("...filter {:10} estimated size {:.0} bytes", filter.to_string(), bits / 8.0);
+        if bits < best_bits * MIN_GAIN_FACTOR {
+            best_bits = bits;
+            best_filter = *filter;
+        }
+    }
+    best_filter
+}
+
+/// Takes evenly spaced chunks of the message. The chunks start at multiples
+/// of 8 so that the samples of the numeric data stay aligned.
+fn get_sample(buffer_in: &[u8]) -> Vec<u8> {
+    if buffer_in.len() <= SAMPLE_CHUNK_SIZE * SAMPLE_MAX_CHUNKS {
+        return buffer_in.to_vec();
+    }
+    let spacing = (buffer_in.len() / SAMPLE_MAX_CHUNKS) & !7;
+    let mut sample = Vec::with_capacity(SAMPLE_CHUNK_SIZE * SAMPLE_MAX_CHUNKS);
+    for chunk in 0..SAMPLE_MAX_CHUNKS {
+        let start = chunk * spacing;
+        sample.extend_from_slice(&buffer_in[start..start + SAMPLE_CHUNK_SIZE]);
+    }
+    sample
+}
+
+/// Total bits of the order zero entropy of the bytes in the buffer.
+fn entropy_bits(buffer: &[u8]) -> f64 {
+    counts_entropy_bits(& histogram::count_bytes(buffer))
+}
+
+/// Total bits of the order zero entropy of the counts of the bytes.
+fn counts_entropy_bits(map_freq: &[usize; 256]) -> f64 {
+    let total = map_freq.iter().sum::<usize>() as f64;
+    map_freq.iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let count = count as f64;
+            - count * (count / total).log2()
+        })
+        .sum()
+}
@@ -0,0 +1,253 @@
+// tANS coder, a table based asymmetric numeral system like the FSE of zstd.
+//
+// The frequencies of the symbols are normalized so that they sum to a power
+// of two, L = 2^table_log, and each symbol gets as many slots in a table of
+// L states as it's normalized frequency. The state of the coder is a number
+// between L and 2L, coding a symbol takes the state to one of the slots of
+// the symbol and sends the low bits of the state to the output. Like the
+// range coder a symbol costs close to -log2(probability) bits, and like the
+// Huffman codes the decoding is a table lookup and a read of some bits.
+//
+// The symbols are coded from the last to the first, and the bits are read
+// back from the end of the bit stream, so the decoder gets them from the
+// first symbol to the last. The coded data is:
+//
+//   4 bytes   number of symbols in the table
+//   1 byte    number of bytes of each symbol
+//   1 byte    table_log
+//   entries   the symbol and it's normalized frequency minus one, in 2 bytes
+//   8 bytes   number of symbols of the message
+//   the bit stream, ending with a 1 bit after the last bit
+
+use crate::alphabet::{Symbol, SymbolType};
+use crate::coder::{read_freq_table, read_symbol_counter, scale_counts};
+use crate::error::{bytes_at, Error, Result};
+
+const MIN_TABLE_LOG: u32 = 11;
+const MAX_TABLE_LOG: u32 = 16;
+
+/// Each symbol needs at least one slot in the table, and some precision left
+/// for the frequent symbols.
+pub const MAX_SYMBOLS: usize = 1 << (MAX_TABLE_LOG - 1);
+
+fn floor_log2(value: u32) -> u32 {
+    31 - value.leading_zeros()
+}
+
+/// The table of symbols, the normalized frequencies and the slots of each
+/// symbol in the table of states.
+struct FseTable {
+    vec_symbols: Vec<Symbol>,
+    vec_norm:    Vec<u32>,
+    /// The symbol index of each of the L slots.
+    vec_slots:   Vec<u32>,
+}
+
+impl FseTable {
+
+    fn new(table_log: u32, vec_symbols: Vec<Symbol>, vec_norm: Vec<u32>) -> FseTable {
+        // Spread the symbols over the table, the step is odd so it passes
+        // over all the slots.
+        let table_size = 1_usize << table_log;
+        let mask = table_size - 1;
+        let step = (table_size >> 1) + (table_size >> 3) + 3;
+        let mut vec_slots: Vec<u32> = vec![0; table_size];
+        let mut pos = 0;
+        for (index, norm) in vec_norm.iter().enumerate() {
+            for _ in 0..*norm {
+                vec_slots[pos] = index as u32;
+                pos = (pos + step) & mask;
+            }
+        }
+        FseTable { vec_symbols, vec_norm, vec_slots }
+    }
+}
+
+/// Encodes the message with the tANS coder. Returns the number of bytes of
+/// the table, or None, and writes nothing, if the alphabet has too many symbols.
+pub fn encode_the_data<S: SymbolType>(vec_counts: &[(Symbol, usize)], buffer_in: &[S], buffer_out: & mut Vec<u8>) -> Option<usize> {
+    if vec_counts.len() > MAX_SYMBOLS {
+        return None;
+    }
+    // A block too short for a symbol of the alphabet has no counts, the
+    // table still needs one symbol to be valid.
+    let vec_counts = if vec_counts.is_empty() { &[(0, 1)][..] } else { vec_counts };
+    // A bigger table gives more precision to the rare symbols, it only costs
+    // time to build, so it grows with the message up to 2^MAX_TABLE_LOG.
+    let log_message = floor_log2(buffer_in.len().clamp(1, 1 << MAX_TABLE_LOG) as u32);
+    let log_symbols = floor_log2(vec_counts.len() as u32) + 2;
+    let table_log = log_message.max(log_symbols).clamp(MIN_TABLE_LOG, MAX_TABLE_LOG);
+    let table_size = 1_u32 << table_log;
+    let vec_norm = scale_counts(vec_counts, table_size);
+    let vec_symbols: Vec<Symbol> = vec_counts.iter().map(|(symbol, _count)| *symbol).collect();
+    let fse_table = FseTable::new(table_log, vec_symbols, vec_norm);
+
+    // Write the table.
+    let table_start = buffer_out.len();
+    let max_symbol = fse_table.vec_symbols.iter().max().copied().unwrap_or(0);
+    let width: usize = if max_symbol > 0xFFFF { 4 } else if max_symbol > 0xFF { 2 } else { 1 };
+    buffer_out.extend_from_slice(& (fse_table.vec_symbols.len() as u32).to_be_bytes());
+    buffer_out.push(width as u8);
+    buffer_out.push(table_log as u8);
+    for (symbol, norm) in fse_table.vec_symbols.iter().zip(fse_table.vec_norm.iter()) {
+        buffer_out.extend_from_slice(& symbol.to_be_bytes()[4 - width..]);
+        buffer_out.extend_from_slice(& ((norm - 1) as u16).to_be_bytes());
+    }
+    let table_len = buffer_out.len() - table_start;
+    buffer_out.extend_from_slice(& (buffer_in.len() as u64).to_be_bytes());
+
+    // The encoding table, for each symbol the states of it's slots in
+    // increasing order, starting at the cumulative frequency of the symbol.
+    let mut vec_cum: Vec<u32> = Vec::with_capacity(fse_table.vec_norm.len());
+    let mut cum = 0;
+    for norm in &fse_table.vec_norm {
+        vec_cum.push(cum);
+        cum += norm;
+    }
+    let mut vec_next: Vec<u32> = vec_cum.clone();
+    let mut vec_states: Vec<u32> = vec![0; table_size as usize];
+    for (slot, index) in fse_table.vec_slots.iter().enumerate() {
+        vec_states[vec_next[*index as usize] as usize] = table_size + slot as u32;
+        vec_next[*index as usize] += 1;
+    }
+
+    // Maps each symbol to it's index in the table.
+    let mut map_symbol_index: Vec<usize> = vec![0; max_symbol as usize + 1];
+    for (index, symbol) in fse_table.vec_symbols.iter().enumerate() {
+        map_symbol_index[*symbol as usize] = index;
+    }
+
+    let mut bit_writer = BitWriter::new(buffer_out);
+    let mut state: u32 = table_size;
+    for symbol in buffer_in.iter().rev() {
+        let index = map_symbol_index[symbol.to_symbol() as usize];
+        let norm = fse_table.vec_norm[index];
+        // The number of low bits that take the state to between norm and 2 norm.
+        let high_bits = table_log - floor_log2(norm);
+        let nb_bits = high_bits - ((state >> high_bits) < norm) as u32;
+        bit_writer.write_bits(state & ((1 << nb_bits) - 1), nb_bits);
+        state = vec_states[(vec_cum[index] + (state >> nb_bits) - norm) as usize];
+    }
+    bit_writer.write_bits(state - table_size, table_log);
+    bit_writer.finish();
+
+    progress!("\n...symbol_counter or original file byte size {} ", buffer_in.len());
+    Some(table_len)
+}
+
+/// Decodes the message coded with the tANS coder, of at most max_symbols
+/// symbols.
+pub fn decode_the_data<S: SymbolType>(buffer_in: &[u8], buffer_out: & mut Vec<S>, max_symbols: usize) -> Result<()> {
+    let table_header = bytes_at(buffer_in, 0, 6)
+        .ok_or(Error::BadTable("the frequency table is cut short"))?;
+    let num_entries = u32::from_be_bytes([table_header[0], table_header[1], table_header[2], table_header[3]]) as usize;
+    let width = table_header[4] as usize;
+    let table_log = table_header[5] as u32;
+    if !(MIN_TABLE_LOG..=MAX_TABLE_LOG).contains(&table_log) {
+        return Err(Error::BadTable("the table_log of the tANS table isn't valid"));
+    }
+    let (vec_symbols, vec_norm, mut pos) = read_freq_table(buffer_in, 6, num_entries, width, 1 << table_log)?;
+    let fse_table = FseTable::new(table_log, vec_symbols, vec_norm);
+
+    let symbol_counter = read_symbol_counter(buffer_in, pos, max_symbols)?;
+    pos += 8;
+
+    progress!("\n...symbol_counter or original file byte size {} ", symbol_counter);
+
+    // The decoding table, for each slot the symbol, the number of bits to
+    // read and the base of the next state.
+    let table_size = 1_u32 << table_log;
+    let mut vec_next: Vec<u32> = fse_table.vec_norm.clone();
+    let mut vec_decode: Vec<(Symbol, u32, u32)> = Vec::with_capacity(table_size as usize);
+    for index in &fse_table.vec_slots {
+        let y = vec_next[*index as usize];
+        vec_next[*index as usize] += 1;
+        let nb_bits = table_log - floor_log2(y);
+        let base = (y << nb_bits) - table_size;
+        vec_decode.push((fse_table.vec_symbols[*index as usize], nb_bits, base));
+    }
+
+    buffer_out.reserve(symbol_counter);
+    let mut bit_reader = BitReader::new(& buffer_in[pos..])?;
+    let mut state = bit_reader.read_bits(table_log);
+    for _ in 0..symbol_counter {
+        let (symbol, nb_bits, base) = vec_decode[state as usize];
+        buffer_out.push(S::from_symbol(symbol));
+        state = base + bit_reader.read_bits(nb_bits);
+    }
+    if bit_reader.is_overrun {
+        return Err(Error::PayloadUnderrun("the tANS bit stream ends before the last symbol"));
+    }
+    Ok(())
+}
+
+/// Writes the bits from the least significant, the first bits go to the
+/// lowest bits of the first byte.
+struct BitWriter<'a> {
+    acc:        u64,
+    nb_acc:     u32,
+    buffer_out: &'a mut Vec<u8>,
+}
+
+impl<'a> BitWriter<'a> {
+
+    fn new(buffer_out: &'a mut Vec<u8>) -> BitWriter<'a> {
+        BitWriter { acc: 0, nb_acc: 0, buffer_out }
+    }
+
+    fn write_bits(& mut self, value: u32, nb_bits: u32) {
+        self.acc |= (value as u64) << self.nb_acc;
+        self.nb_acc += nb_bits;
+        while self.nb_acc >= 8 {
+            self.buffer_out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.nb_acc -= 8;
+        }
+    }
+
+    /// Writes the 1 bit that marks the end and the last byte.
+    fn finish(& mut self) {
+        self.write_bits(1, 1);
+        if self.nb_acc > 0 {
+            self.buffer_out.push(self.acc as u8);
+        }
+    }
+}
+
+/// Reads the bits backwards, from the end of the bit stream.
+struct BitReader<'a> {
+    buffer_in:  &'a [u8],
+    /// Number of bits still to read.
+    pos:        usize,
+    /// More bits were read than the stream has, they were read as zeros.
+    is_overrun: bool,
+}
+
+impl<'a> BitReader<'a> {
+
+    fn new(buffer_in: &'a [u8]) -> Result<BitReader<'a>> {
+        // The last 1 bit marks the end.
+        let last_byte = match buffer_in.last() {
+            Some(&byte) if byte != 0 => byte,
+            _ => return Err(Error::PayloadUnderrun("the tANS bit stream has no end mark")),
+        };
+        let pos = (buffer_in.len() - 1) * 8 + floor_log2(last_byte as u32) as usize;
+        Ok(BitReader { buffer_in, pos, is_overrun: false })
+    }
+
+    fn read_bits(& mut self, nb_bits: u32) -> u32 {
+        if self.pos < nb_bits as usize {
+            self.is_overrun = true;
+            self.pos = 0;
+            return 0;
+        }
+        self.pos -= nb_bits as usize;
+        let mut value: u64 = 0;
+        let first_byte = self.pos / 8;
+        for i in 0..4 {
+            let byte = *self.buffer_in.get(first_byte + i).unwrap_or(&0);
+            value |= (byte as u64) << (8 * i);
+        }
+        ((value >> (self.pos % 8)) & ((1 << nb_bits) - 1)) as u32
+    }
+}
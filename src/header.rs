@@ -0,0 +1,106 @@
+// The header at the beginning of every .johnny file.
+//
+//   bytes 0..4   magic "JHNY"
+//   byte  4      format version
+//   byte  5      filter identifier
+//   byte  6      filter parameter (stride or element size)
+//   bytes 7..11  number of blocks, big endian
+//   bytes 11..15 CRC32 of the original message, big endian
+//
+// After the header come the blocks, each one with it's own block header and
+// body. The body is the alphabet data, followed by the Huffman mapping table
+// and the coded data, or the frequency table and the coded data of the range
+// coder or of the tANS coder, or for a stored block the bytes of the block.
+// An empty message has no blocks, every .johnny file has the header, even
+// the one of a message of a few bytes.
+//
+//   byte  0      alphabet identifier
+//   byte  1      coder identifier
+//   bytes 2..6   number of bytes of the block, after the filter, big endian
+//   bytes 6..10  number of bytes of the body, big endian
+
+use crate::alphabet::Alphabet;
+use crate::coder::Coder;
+use crate::error::{Error, Result};
+use crate::filter::Filter;
+
+pub const MAGIC: &[u8; 4] = b"JHNY";
+pub const FORMAT_VERSION: u8 = 9;
+pub const HEADER_SIZE: usize = 15;
+pub const BLOCK_HEADER_SIZE: usize = 10;
+
+#[derive(Debug)]
+pub struct Header {
+    pub filter:     Filter,
+    pub num_blocks: u32,
+    pub checksum:   u32,
+}
+
+#[derive(Debug)]
+pub struct BlockHeader {
+    pub alphabet:  Alphabet,
+    pub coder:     Coder,
+    pub raw_len:   u32,
+    pub coded_len: u32,
+}
+
+impl Header {
+
+    pub fn write_to_byte_buffer(&self, buffer_out: & mut Vec<u8>) {
+        let (filter_id, filter_param) = self.filter.to_header_bytes();
+        buffer_out.extend_from_slice(MAGIC);
+        buffer_out.push(FORMAT_VERSION);
+        buffer_out.push(filter_id);
+        buffer_out.push(filter_param);
+        buffer_out.extend_from_slice(& self.num_blocks.to_be_bytes());
+        buffer_out.extend_from_slice(& self.checksum.to_be_bytes());
+    }
+
+    /// Reads the header at the start of the buffer.
+    pub fn read_from_byte_buffer(buffer_in: &[u8]) -> Result<Header> {
+        if crate::encryption::is_encrypted(buffer_in) {
+            return Err(Error::Encrypted("use --password-file or --key-file"));
+        }
+        if buffer_in.len() < MAGIC.len() || &buffer_in[0..4] != MAGIC {
+            return Err(Error::NotJohnny);
+        }
+        if buffer_in.len() < 5 {
+            return Err(Error::TruncatedHeader("the .johnny header is cut short"));
+        }
+        if buffer_in[4] != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion(buffer_in[4]));
+        }
+        if buffer_in.len() < HEADER_SIZE {
+            return Err(Error::TruncatedHeader("the .johnny header is cut short"));
+        }
+        let filter = Filter::from_header_bytes(buffer_in[5], buffer_in[6])
+            .ok_or(Error::BadHeader("unknown filter"))?;
+        let num_blocks = u32::from_be_bytes([buffer_in[7], buffer_in[8], buffer_in[9], buffer_in[10]]);
+        let checksum = u32::from_be_bytes([buffer_in[11], buffer_in[12], buffer_in[13], buffer_in[14]]);
+        Ok(Header { filter, num_blocks, checksum })
+    }
+}
+
+impl BlockHeader {
+
+    pub fn write_to_byte_buffer(&self, buffer_out: & mut Vec<u8>) {
+        buffer_out.push(self.alphabet.to_header_byte());
+        buffer_out.push(self.coder.to_header_byte());
+        buffer_out.extend_from_slice(& self.raw_len.to_be_bytes());
+        buffer_out.extend_from_slice(& self.coded_len.to_be_bytes());
+    }
+
+    /// Reads the block header at the start of the buffer.
+    pub fn read_from_byte_buffer(buffer_in: &[u8]) -> Result<BlockHeader> {
+        if buffer_in.len() < BLOCK_HEADER_SIZE {
+            return Err(Error::TruncatedHeader("a block header is cut short"));
+        }
+        let alphabet = Alphabet::from_header_byte(buffer_in[0])
+            .ok_or(Error::BadHeader("unknown alphabet"))?;
+        let coder = Coder::from_header_byte(buffer_in[1])
+            .ok_or(Error::BadHeader("unknown coder"))?;
+        let raw_len = u32::from_be_bytes([buffer_in[2], buffer_in[3], buffer_in[4], buffer_in[5]]);
+        let coded_len = u32::from_be_bytes([buffer_in[6], buffer_in[7], buffer_in[8], buffer_in[9]]);
+        Ok(BlockHeader { alphabet, coder, raw_len, coded_len })
+    }
+}
@@ -0,0 +1,97 @@
+// Counts of the symbols of the message, for the Huffman tree and the
+// frequency tables of the other coders.
+//
+// With a single table of counters a run of the same byte increments the same
+// counter again and again, and each increment waits for the store of the
+// one before. With NUM_TABLES tables the bytes that follow each other go to
+// different tables and the increments can run at the same time, the tables
+// are added at the end.
+
+use crate::alphabet::SymbolType;
+
+/// Tables of count_bytes, one for each byte of a 64 bit word.
+const NUM_TABLES: usize = 8;
+
+/// Tables of count_symbols, the tables of the bigger alphabets take more of
+/// the cache.
+const NUM_SYMBOL_TABLES: usize = 4;
+
+/// Size of each chunk of the sampled estimate.
+const SAMPLE_CHUNK_SIZE: usize = 4096;
+
+/// The count of each of the 256 byte values.
+pub fn count_bytes(buffer_in: &[u8]) -> [usize; 256] {
+    let mut tables = [[0_usize; 256]; NUM_TABLES];
+    let mut chunks = buffer_in.chunks_exact(NUM_TABLES);
+    for chunk in & mut chunks {
+        // 8 bytes at a time, one for each table.
+        let word = u64::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3], chunk[4], chunk[5], chunk[6], chunk[7]]);
+        tables[0][(word & 0xFF) as usize] += 1;
+        tables[1][(word >> 8 & 0xFF) as usize] += 1;
+        tables[2][(word >> 16 & 0xFF) as usize] += 1;
+        tables[3][(word >> 24 & 0xFF) as usize] += 1;
+        tables[4][(word >> 32 & 0xFF) as usize] += 1;
+        tables[5][(word >> 40 & 0xFF) as usize] += 1;
+        tables[6][(word >> 48 & 0xFF) as usize] += 1;
+        tables[7][(word >> 56) as usize] += 1;
+    }
+    for &byte in chunks.remainder() {
+        tables[0][byte as usize] += 1;
+    }
+
+    let mut counts = tables[0];
+    for table in & tables[1..] {
+        for (count, table_count) in counts.iter_mut().zip(table.iter()) {
+            *count += table_count;
+        }
+    }
+    counts
+}
+
+/// Quick estimate of the counts of the bytes, from evenly spaced chunks of
+/// the message that sum up to max_sample bytes.
+pub fn count_bytes_sampled(buffer_in: &[u8], max_sample: usize) -> [usize; 256] {
+    if buffer_in.len() <= max_sample {
+        return count_bytes(buffer_in);
+    }
+    let num_chunks = (max_sample / SAMPLE_CHUNK_SIZE).max(1);
+    let spacing = buffer_in.len() / num_chunks;
+    let mut counts = [0_usize; 256];
+    for chunk in 0..num_chunks {
+        let start = chunk * spacing;
+        let chunk_counts = count_bytes(& buffer_in[start..start + SAMPLE_CHUNK_SIZE.min(spacing)]);
+        for (count, chunk_count) in counts.iter_mut().zip(chunk_counts.iter()) {
+            *count += chunk_count;
+        }
+    }
+    counts
+}
+
+/// The count of each symbol of the alphabet. The bytes use count_bytes, and
+/// the other alphabets of up to 256 symbols use NUM_SYMBOL_TABLES tables.
+pub fn count_symbols<S: SymbolType>(buffer_in: &[S], alphabet_size: usize) -> Vec<usize> {
+    if let Some(bytes) = S::as_bytes(buffer_in) {
+        return count_bytes(bytes)[..alphabet_size].to_vec();
+    }
+    if alphabet_size <= 256 {
+        let mut tables = vec![[0_usize; 256]; NUM_SYMBOL_TABLES];
+        let mut chunks = buffer_in.chunks_exact(NUM_SYMBOL_TABLES);
+        for chunk in & mut chunks {
+            for (table, elem) in tables.iter_mut().zip(chunk.iter()) {
+                table[elem.to_symbol() as usize] += 1;
+            }
+        }
+        for elem in chunks.remainder() {
+            tables[0][elem.to_symbol() as usize] += 1;
+        }
+        return (0..alphabet_size)
+            .map(|symbol| tables.iter().map(|table| table[symbol]).sum())
+            .collect();
+    }
+
+    let mut map_freq: Vec<usize> = vec![0; alphabet_size];
+    for elem in buffer_in {
+        map_freq[elem.to_symbol() as usize] += 1;
+    }
+    map_freq
+}
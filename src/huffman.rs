@@ -0,0 +1,550 @@
+// The Huffman codes of the symbols of a block.
+//
+// The mapping table counts the symbols, builds the Huffman tree with a
+// priority queue, assigns the canonical codes from the lengths of the codes
+// and writes and reads the table of the lengths. The codes are written in
+// the interleaved streams of the streams module.
+
+use std::collections::BinaryHeap;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+
+// The fastest HashMap for Rust. HashBrown a drop in replacement for std HashMap.
+use hashbrown::HashMap;
+
+use crate::alphabet::{Symbol, SymbolType};
+use crate::error::{bytes_at, Error, Result};
+use crate::histogram;
+use crate::streams;
+
+enum Elem {
+    Node(NodeType),
+    Leaf(LeafType),
+} 
+
+struct NodeType {
+    total_count: usize,
+    left:        Box<Elem>,
+    right:       Box<Elem>,
+}
+
+struct LeafType {
+    pos: Symbol,
+    count: usize,
+}
+
+impl Elem {
+    fn count(&self) -> usize {
+        match self {
+            Elem::Node( NodeType { total_count, left: _, right: _ } ) => *total_count,
+            Elem::Leaf( LeafType { pos: _, count } )                   => *count,
+        }
+    }
+}
+
+/// Element of the priority queue, the lowest count comes out first. The order
+/// of insertion breaks the ties, so the tree is always the same.
+struct QueueElem {
+    count: usize,
+    order: usize,
+    elem:  Elem,
+}
+
+impl Ord for QueueElem {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.count.cmp(&self.count).then(other.order.cmp(&self.order))
+    }
+}
+
+impl PartialOrd for QueueElem {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for QueueElem {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueueElem {}
+
+/// The heading of the mapping table, the 32 bit offset of the end of the
+/// table and the number of bytes of each symbol.
+pub const TABLE_HEADER_SIZE: usize = 5;
+
+pub struct MappingTable {
+    vec_elem_count:  Vec<Elem>,        // Vec<(Symbol, usize)>,
+    pub print_text_char: bool,
+    map_encoding:    HashMap< Symbol, String >,
+    map_decoding:    HashMap< String, Symbol >,
+}
+
+impl MappingTable {
+
+    pub fn new() -> MappingTable {
+        MappingTable {
+            vec_elem_count:  Vec::new(),
+            print_text_char: true,
+            map_encoding:    HashMap::new(),
+            map_decoding:    HashMap::new(),
+        }
+    }
+
+
+    //******************
+    //* Compress methods
+    //******************
+
+    /// 2. Determine the frequency of the symbols (different bytes) in the input buffer.
+    pub fn get_buffer_byte_symbols_freq<S: SymbolType>(& mut self, buffer_in: &[S], alphabet_size: usize) {
+        let map_freq: Vec<usize> = histogram::count_symbols(buffer_in, alphabet_size);
+        // let total_bytes = buffer_in.len();
+        self.set_symbol_counts(& map_freq);
+    }
+
+    /// The count of each symbol, the symbols with count zero get no code.
+    pub fn set_symbol_counts(& mut self, map_freq: &[usize]) {
+        self.vec_elem_count.clear();
+        for (pos, e) in map_freq.iter().enumerate() {
+            if *e != 0 {
+                self.vec_elem_count.push(Elem::Leaf(
+                    LeafType {
+                        pos: pos as Symbol,
+                        count: *e,
+                    }
+                    ) );
+            }
+        }
+    }
+
+    /// The count of each symbol of the message, for the range coder.
+    pub fn get_symbol_counts(& self) -> Vec<(Symbol, usize)> {
+        self.vec_elem_count.iter()
+            .map(|elem| match elem {
+                Elem::Leaf( LeafType { pos, count } ) => (*pos, *count),
+                Elem::Node( NodeType { total_count, left: _, right: _ } ) => unreachable!("node with count {}", total_count),
+            })
+            .collect()
+    }
+
+    /// 3. By using a priority queue and the Huffman coding tree find the best    
+    ///    coding for each symbol of the message. Create a table for the code.
+    ///    This table inverted will also have to be known in the decoding phase.
+    pub fn generate_huffman_code(& mut self) {
+        
+        // A block too short for a symbol of the alphabet has no code.
+        if self.vec_elem_count.is_empty() {
+            return;
+        }
+        // A single symbol gets the empty code, of length zero, the block is
+        // a run of the symbol and only the number of symbols is written.
+        if self.vec_elem_count.len() == 1 {
+            if let Elem::Leaf( LeafType {pos, count: _} ) = self.vec_elem_count[0] {
+                self.assign_canonical_codes(vec![(pos, 0)]);
+                return;
+            }
+        }
+
+        let mut queue: BinaryHeap<QueueElem> = self.vec_elem_count.drain(..)
+            .enumerate()
+            .map(|(order, elem)| QueueElem { count: elem.count(), order, elem })
+            .collect();
+        let mut order = queue.len();
+
+        while queue.len() >= 2 {
+            // Take the lowest element.
+            let elem_0 = queue.pop().unwrap().elem;
+            // Take the second lowest element.
+            let elem_1 = queue.pop().unwrap().elem;
+
+            let node = Elem::Node( NodeType{
+                total_count: elem_0.count() + elem_1.count(),
+                left:        Box::new(elem_0),
+                right:       Box::new(elem_1),
+            });
+
+            queue.push(QueueElem { count: node.count(), order, elem: node });
+            order += 1;
+        }
+        self.vec_elem_count.push(queue.pop().unwrap().elem);
+
+        progress!("...finished generating huffman code tree!");
+        self.print_huffman_tree();
+        self.get_huffman_code_from_tree();        
+    }
+
+    fn get_huffman_code_from_tree(& mut self) {
+        progress!("...get_huffman_code_from_tree:");
+        let curr_node: & Elem = & self.vec_elem_count[0];
+        let start_code = "".to_string();
+        let mut map: HashMap< Symbol, String > = HashMap::new();
+        self.transverse_tree_get_huffman_codes(curr_node, start_code, & mut map );
+
+        // Only the length of the codes of the tree are kept, the codes are
+        // then given in canonical order, so that the table only needs the
+        // length of the code of each symbol.
+        let vec_lengths: Vec<(Symbol, usize)> = map.iter()
+            .map(|(symbol, code)| (*symbol, code.len()))
+            .collect();
+        self.assign_canonical_codes(vec_lengths);
+    }
+
+    /// Transverse the tree recursively.
+    fn transverse_tree_get_huffman_codes(& self, curr_elem: & Elem, code: String, map_encoding_p: & mut HashMap< Symbol, String > ) {
+        match curr_elem {
+            Elem::Node( NodeType { total_count: _, left, right} ) => {
+                let new_code_left = code.clone() + "0";
+                self.transverse_tree_get_huffman_codes(left,new_code_left, map_encoding_p);
+                let new_code_right = code.clone() + "1";
+                self.transverse_tree_get_huffman_codes(right,new_code_right, map_encoding_p);
+            }
+            Elem::Leaf( LeafType { pos, count: _ } ) => {
+                let symbol: Symbol = *pos;
+                let huffman_code = code; 
+                map_encoding_p.insert(symbol, huffman_code);
+            }
+        }
+    }
+
+    /// Gives the canonical Huffman codes from the length of the code of each
+    /// symbol. The symbols are ordered by code length and then by symbol, the
+    /// first gets the code all zeros and each next one gets the previous code
+    /// plus one, with zeros added to the right when the length grows.
+    /// Fills in the map encoding and the map decoding.
+    fn assign_canonical_codes(& mut self, mut vec_lengths: Vec<(Symbol, usize)>) {
+        vec_lengths.sort_by(|(symbol_a, len_a), (symbol_b, len_b)| len_a.cmp(len_b).then(symbol_a.cmp(symbol_b)));
+
+        self.map_encoding.clear();
+        self.map_decoding.clear();
+        let mut code: Vec<u8> = Vec::new();
+        for (symbol, len) in vec_lengths {
+            if !code.is_empty() {
+                // Plus one.
+                let mut pos = code.len();
+                while pos > 0 {
+                    pos -= 1;
+                    if code[pos] == b'0' {
+                        code[pos] = b'1';
+                        break;
+                    }
+                    code[pos] = b'0';
+                }
+            }
+            while code.len() < len {
+                code.push(b'0');
+            }
+            let code_string = String::from_utf8(code.clone()).unwrap();
+            self.map_encoding.insert(symbol, code_string.clone());
+            self.map_decoding.insert(code_string, symbol);
+        }
+    }
+
+    /// Prints the Huffman coding tree.
+    fn print_huffman_tree(& self) {
+        if crate::is_quiet() {
+            return;
+        }
+        println!("...huffman code tree:");
+
+        let curr_node: & Elem = &self.vec_elem_count[0];
+        let offset = 0;
+        self.print_tree_node(curr_node, offset, false, false, "".to_string() );
+    }    
+
+    /// Transverse the tree recursively for printing the nodes and leafs of the tree.
+    fn print_tree_node(& self, curr_elem: & Elem, offset: usize, changed_line: bool, other_leaf: bool, code: String) -> bool {
+        match curr_elem {
+            Elem::Node( NodeType { total_count, left, right} ) => {
+                if changed_line {
+                    let offset_string = " ".to_string().repeat(offset);
+                    print!{"{}", offset_string};
+                }
+                print!(" |-  {:3} -| ", total_count);
+                let new_code_left = code.clone() + "0";
+                let ret_flag = self.print_tree_node(left, offset + 12, false, false, new_code_left);
+                let new_code_right = code.clone() + "1";
+                self.print_tree_node(right,offset + 12, true, ret_flag, new_code_right);
+                false
+            }
+            Elem::Leaf( LeafType { pos, count } ) => {
+                if other_leaf {
+                    let offset_string = " ".to_string().repeat(offset);
+                    print!{"{}", offset_string};
+                }
+                
+                if self.print_text_char {
+                    let mut c = *pos as u8 as char;
+                    if c == '\n' {
+                        c = '\\';
+                    }
+                    progress!(" <-{} {:3}  {} ->", c, count, code);
+                } else {
+                    progress!(" <-{} {:3}  {} ->", pos, count, code);
+                }
+                true
+            }
+        }
+    }
+
+    /// The length of the code of each symbol, in the order of the symbols.
+    pub fn code_lengths(&self) -> Vec<(Symbol, usize)> {
+        let mut vec_lengths: Vec<(Symbol, usize)> = self.map_encoding.iter()
+            .map(|(symbol, code)| (*symbol, code.len()))
+            .collect();
+        vec_lengths.sort_unstable();
+        vec_lengths
+    }
+
+    /// The code of each symbol as a number and it's length in bits, for the
+    /// streams.
+    pub(crate) fn code_values(&self) -> Vec<(Symbol, u64, u32)> {
+        streams::code_values(& self.map_encoding)
+    }
+
+    /// The symbol of a run, if the table has a single symbol with the empty code.
+    fn run_symbol(&self) -> Option<Symbol> {
+        match self.map_encoding.iter().next() {
+            Some((symbol, code)) if self.map_encoding.len() == 1 && code.is_empty() => Some(*symbol),
+            _ => None,
+        }
+    }
+
+    /// Number of bytes that the mapping table will use, with the 32 bit heading.
+    pub fn mapping_table_size(& self) -> usize {
+        TABLE_HEADER_SIZE + self.map_encoding.len() * (symbol_width(& self.map_encoding) + 1)
+    }
+
+    /// 4. Write the table to the beginning of byte buffer and the 32 bit heading,
+    ///    with the start of the data.
+    ///    The table has the number of bytes of each symbol, followed by each
+    ///    symbol and the length of it's code, in the canonical order.
+    pub fn write_mapping_table_to_byte_buffer(& mut self, buffer_out: & mut Vec<u8>) {
+
+        // Fill in the header with zeros.
+        buffer_out.extend_from_slice(&[0; 4]);
+
+        let width = symbol_width(& self.map_encoding);
+        buffer_out.push(width as u8);
+
+        let mut vec_tmp: Vec<(String, Symbol)> = self.map_decoding.iter()
+                    .map(|(k, v)| (k.clone(), *v) )
+                    .collect();
+
+        // Canonical order, by length of the code and then by the code.
+        vec_tmp.sort_by(|(key_a, _val_a), (key_b, _val_b)| key_a.len().cmp(&key_b.len()).then(key_a.cmp(key_b)));
+
+        for (key, value) in & vec_tmp {
+            buffer_out.extend_from_slice(& value.to_be_bytes()[4 - width..]);
+            buffer_out.push(key.len() as u8);
+        }
+
+        progress!("\n map_decoding: \n{:?}\n\n", vec_tmp);
+
+        // Fill in the header with the position of one plus the end of
+        // the header or the position of the start of the compressed data.
+        // The offset has 32 bits, like the length of the block, so any
+        // table fits.
+        let len = u32::try_from(buffer_out.len())
+            .expect("...the mapping table is smaller than the 4 GiB of a block.");
+        buffer_out[0..4].copy_from_slice(& len.to_be_bytes());
+    }
+    
+    /// 5. With the new dictionary, encode the message in bytes to a byte buffer.
+    /// 6. Write the first 8 byte with an usize 64 bit's representing the number
+    ///    of bytes or total symbols in the original file of the message.    
+    ///    Then the jump table and the streams.
+    pub fn encode_the_data<S: SymbolType>(&self, buffer_in: &[S], num_streams: usize, buffer_out: & mut Vec<u8>) {
+
+        let start_2_header = buffer_out.len();
+
+        progress!("\n...start index of data {} ", start_2_header);
+
+        // Fill in the second header, for the 64 bit, 8 bytes number of symbols,
+        // with zeros.
+        buffer_out.push(0);
+        buffer_out.push(0);
+        buffer_out.push(0);
+        buffer_out.push(0);
+
+        buffer_out.push(0);
+        buffer_out.push(0);
+        buffer_out.push(0);
+        buffer_out.push(0);
+
+        // Encode from buffer_in into buffer_out_after the the decoding table,
+        // in num_streams interleaved streams. A run of a single symbol has
+        // no streams, the number of symbols is enough to decode it, so the
+        // block has the same size for any number of symbols.
+        let symbol_counter: usize = buffer_in.len();
+        if self.run_symbol().is_none() {
+            streams::encode_streams(& streams::code_values(& self.map_encoding), buffer_in, num_streams, buffer_out);
+        }
+
+        // Debug: 
+        // progress!();
+        // buffer_out[start_2_header + 8 ..].iter().map(|byte| print!("{:b} ", byte)).count();
+
+
+        // Fill in the second header with the number of symbols in the file data section,
+        // or by other words the number of bytes of the original file.
+        // We need this second header for the deconding fase, because our compressed 
+        // symbols are variable length in size, from zero to n bits.
+        
+        /*
+        let symbol_ct_0: u8 = ((symbol_counter & (0x00FF_usize <<  0)) >>  0) as u8;
+        let symbol_ct_1: u8 = ((symbol_counter & (0x00FF_usize <<  8)) >>  8) as u8;
+        let symbol_ct_2: u8 = ((symbol_counter & (0x00FF_usize << 16)) >> 16) as u8;
+        let symbol_ct_3: u8 = ((symbol_counter & (0x00FF_usize << 24)) >> 24) as u8;
+        let symbol_ct_4: u8 = ((symbol_counter & (0x00FF_usize << 32)) >> 32) as u8;
+        let symbol_ct_5: u8 = ((symbol_counter & (0x00FF_usize << 40)) >> 40) as u8;
+        let symbol_ct_6: u8 = ((symbol_counter & (0x00FF_usize << 48)) >> 48) as u8;
+        let symbol_ct_7: u8 = ((symbol_counter & (0x00FF_usize << 56)) >> 56) as u8;
+                
+        buffer_out[start_2_header + 0] = symbol_ct_7;
+        buffer_out[start_2_header + 1] = symbol_ct_6;
+        buffer_out[start_2_header + 2] = symbol_ct_5;
+        buffer_out[start_2_header + 3] = symbol_ct_4;
+        buffer_out[start_2_header + 4] = symbol_ct_3;
+        buffer_out[start_2_header + 5] = symbol_ct_2;
+        buffer_out[start_2_header + 6] = symbol_ct_1;
+        buffer_out[start_2_header + 7] = symbol_ct_0;
+        */
+
+        // Compressed version.
+        for i in 0..8 {
+            let symbol_ct: u8 = ((symbol_counter & (0x00FF_usize <<  (i*8))) >>  (i*8)) as u8;
+            buffer_out[start_2_header + 7 - i] = symbol_ct;
+        }
+
+        progress!("\n...symbol_counter or original file byte size {} ", symbol_counter);
+
+    }
+
+    //********************
+    //* Decompress methods
+    //********************
+
+    /// 2. Extract the symbols coding table to an internal representation. That is
+    ///    the one with the Huffman coding inverted for decoding. 
+    ///    Returns the start of the coded data, after the table.
+    pub fn read_mapping_table_from_byte_buffer(&mut self, buffer_in: &[u8]) -> Result<usize> {
+
+        // Read the first header with the position of one plus the end of
+        // the header or the position of the start of the compressed data.
+        if buffer_in.len() < TABLE_HEADER_SIZE {
+            return Err(Error::BadTable("the Huffman mapping table is cut short"));
+        }
+        let header_2_start = u32::from_be_bytes([buffer_in[0], buffer_in[1], buffer_in[2], buffer_in[3]]) as usize;
+        if header_2_start < TABLE_HEADER_SIZE || header_2_start > buffer_in.len() {
+            return Err(Error::BadTable("the end of the Huffman mapping table is out of the block"));
+        }
+
+        progress!("\n...header_1_start index in the .johnny compressed input  {} ", header_2_start);
+
+
+        progress!("\n...decoding table:\n");
+
+        let width = buffer_in[4] as usize;
+        if !(1..=4).contains(&width) || !(header_2_start - TABLE_HEADER_SIZE).is_multiple_of(width + 1) {
+            return Err(Error::BadTable("the entries of the Huffman mapping table aren't valid"));
+        }
+        let mut vec_lengths: Vec<(Symbol, usize)> = Vec::new();
+        for entry in buffer_in[TABLE_HEADER_SIZE..header_2_start].chunks_exact(width + 1) {
+            let mut symbol: Symbol = 0;
+            for &byte in & entry[..width] {
+                symbol = symbol << 8 | byte as Symbol;
+            }
+            let len = entry[width] as usize;
+            if !self.print_text_char {
+                progress!("{} -> {}", symbol, len);
+            }
+            vec_lengths.push((symbol, len));
+        }
+        check_code_lengths(& vec_lengths)?;
+        self.assign_canonical_codes(vec_lengths);
+        
+        Ok(header_2_start)
+    }
+
+    // 3. Read the 32 bit header with the index (of the byte) of the start of
+    //    the data in the .johnny file. Read the second header with the number
+    //    of original symbols, or we could say original bytes. 
+    //    Apply the decoding table to the coded message bytes, buffer_in, and decode or
+    //    decompress it into a binary buffer_out. 
+    //    The block has at most max_symbols symbols.
+    pub fn decode_the_data<S: SymbolType>(&mut self, buffer_in: &[u8], buffer_out: & mut Vec<S>, header_2_start: usize, max_symbols: usize) -> Result<()> {
+
+        // Read the second header with the number of symbols or bytes of the original
+        // file. This is important because the number of bit's for each compressed symbol
+        // varies with the symbol and implements exactly a variable minimal Huffman encoding.   
+
+        // Compressed version.
+        let header_2 = bytes_at(buffer_in, header_2_start, 8)
+            .ok_or(Error::PayloadUnderrun("the number of symbols is cut short"))?;
+        let mut symbol_counter: usize = 0;
+        for i in 0..8 {
+            let symbol_ct = header_2[7 - i];
+            symbol_counter |= (symbol_ct as usize) << (i*8);
+        }
+
+        progress!("\n...symbol_counter or original file byte size {} ", symbol_counter);
+        if symbol_counter > max_symbols {
+            return Err(Error::PayloadUnderrun("the number of symbols is bigger than the block"));
+        }
+        // A run of a single symbol, without streams.
+        if let Some(symbol) = self.run_symbol() {
+            buffer_out.resize(buffer_out.len() + symbol_counter, S::from_symbol(symbol));
+            return Ok(());
+        }
+        // Each code has at least one bit.
+        if symbol_counter / 8 > buffer_in.len() - header_2_start - 8 {
+            return Err(Error::PayloadUnderrun("the Huffman streams are shorter than the number of symbols"));
+        }
+
+        // We obtain the data sub_range slice, with the streams, and decode it
+        // with the table of the codes.
+        let sub_range_buffer_in = &buffer_in[header_2_start + 8 ..];
+        streams::decode_streams(& streams::code_values(& self.map_encoding), sub_range_buffer_in, symbol_counter, buffer_out)?;
+    
+        progress!();
+        Ok(())
+    }
+
+}
+
+/// The lengths of a table read from a file must give a prefix code, each
+/// symbol once and the Kraft sum of 2^-len not bigger than one. Otherwise
+/// the canonical codes of the lengths overflow and some codes are the
+/// prefix of others. Only the single symbol of a run has the length zero.
+fn check_code_lengths(vec_lengths: &[(Symbol, usize)]) -> Result<()> {
+    if let [(_symbol, 0)] = vec_lengths {
+        return Ok(());
+    }
+    let mut kraft_sum: u128 = 0;
+    for (_symbol, len) in vec_lengths {
+        if *len == 0 || *len > streams::MAX_CODE_LEN as usize {
+            return Err(Error::BadTable("a Huffman code length is out of range"));
+        }
+        kraft_sum += 1_u128 << (streams::MAX_CODE_LEN as usize - len);
+    }
+    if kraft_sum > 1_u128 << streams::MAX_CODE_LEN {
+        return Err(Error::BadTable("the Huffman code lengths aren't a prefix code"));
+    }
+    let mut vec_symbols: Vec<Symbol> = vec_lengths.iter().map(|(symbol, _len)| *symbol).collect();
+    vec_symbols.sort_unstable();
+    if vec_symbols.windows(2).any(|pair| pair[0] == pair[1]) {
+        return Err(Error::BadTable("a symbol is twice in the Huffman mapping table"));
+    }
+    Ok(())
+}
+
+/// Number of bytes needed to write the biggest symbol of the table.
+fn symbol_width(map_encoding: & HashMap< Symbol, String >) -> usize {
+    match map_encoding.keys().max() {
+        Some(&max) if max > 0xFFFF => 4,
+        Some(&max) if max > 0xFF   => 2,
+        _                          => 1,
+    }
+}
@@ -0,0 +1,68 @@
+// The input file, memory mapped when it's a regular file.
+//
+// A mapped file isn't copied to the heap, the histogram pass and the encode
+// pass read it from the page cache. Pipes and special files, and the files
+// that can't be mapped, are read into a buffer until the end of the file.
+// A file that another process truncates while it's mapped gives a SIGBUS on
+// the pages after the new end, in place of an error, the program is stopped
+// by the signal, as other tools that map their input. The --no-mmap option
+// reads the file into a buffer for the files that can be truncated.
+// The stamp of a regular file, it's length and modification time, is taken
+// before and after it's used, a file that changed gives an error in place of
+// a wrong output.
+
+use std::fs::{self, File};
+use std::ops::Deref;
+use std::time::SystemTime;
+
+use memmap2::Mmap;
+
+pub enum Input {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl Deref for Input {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Input::Mapped(mmap)  => mmap,
+            Input::Owned(buffer) => buffer,
+        }
+    }
+}
+
+/// Maps the file, or returns None if it isn't a regular file with some bytes
+/// or the map fails.
+pub fn map_file(filename: &str) -> Option<Input> {
+    let file = File::open(filename).ok()?;
+    let metadata = file.metadata().ok()?;
+    if !metadata.is_file() || metadata.len() == 0 {
+        return None;
+    }
+    // Safety: the file is only read, if another process changes it while it's
+    // mapped the stamp detects it, but a truncated file gives a SIGBUS on the
+    // pages after it's new end.
+    let mmap = unsafe { Mmap::map(& file) }.ok()?;
+    Some(Input::Mapped(mmap))
+}
+
+/// The length and the modification time of a regular file, to detect a file
+/// that another process changes while it's read.
+#[derive(Debug, PartialEq)]
+pub struct FileStamp {
+    pub len:  u64,
+    modified: Option<SystemTime>,
+}
+
+/// The stamp of the file, or None if it isn't a regular file with some
+/// bytes. The files of /proc have the length zero and any number of bytes,
+/// they can't be checked.
+pub fn file_stamp(filename: &str) -> Option<FileStamp> {
+    let metadata = fs::metadata(filename).ok()?;
+    if !metadata.is_file() || metadata.len() == 0 {
+        return None;
+    }
+    Some(FileStamp { len: metadata.len(), modified: metadata.modified().ok() })
+}
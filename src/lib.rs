@@ -0,0 +1,487 @@
+// Huffman codes - compress and decompress .johnny files.
+//
+// The library with the compression and the decompression of a message in
+// memory, used by the huffman_codes command line and by the benchmarks. See
+// the header of main.rs for the steps of the algorithm.
+//
+// The progress messages, the tables and the Huffman tree are printed to the
+// standard output, unless set_quiet(true) is called.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Stops, or starts again, the printing of the progress messages.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// println! of the progress messages, that set_quiet turns off.
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if !$crate::is_quiet() {
+            println!($($arg)*);
+        }
+    };
+}
+
+pub mod alphabet;
+mod arith;
+pub mod coder;
+pub mod corpus;
+pub mod dictionary;
+pub mod encryption;
+pub mod error;
+pub mod filter;
+mod fse;
+mod header;
+mod histogram;
+mod huffman;
+pub mod limits;
+pub mod parallel;
+pub mod recovery;
+pub mod signature;
+pub mod streams;
+
+use alphabet::{Alphabet, SymbolType};
+use coder::{Coder, CoderMode};
+use dictionary::Dictionary;
+use error::{Error, Result};
+use filter::{Filter, FilterMode};
+use header::{BlockHeader, Header, BLOCK_HEADER_SIZE, HEADER_SIZE};
+use huffman::MappingTable;
+use limits::Limits;
+
+/// The default size of the blocks, each block has it's own tables.
+pub const DEFAULT_BLOCK_SIZE: usize = 4 * 1024 * 1024;
+
+/// The options of the compression, from the command line.
+#[derive(Debug, Clone)]
+pub struct CompressOptions {
+    pub filter:     FilterMode,
+    pub alphabet:   Alphabet,
+    pub coder:      CoderMode,
+    pub block_size: usize,
+    pub streams:    usize,
+    pub threads:    usize,
+    /// The pretrained table of --table, for the blocks of the bytes alphabet.
+    pub dictionary: Option<Dictionary>,
+}
+
+impl Default for CompressOptions {
+    fn default() -> CompressOptions {
+        CompressOptions {
+            filter:     FilterMode::Fixed(Filter::None),
+            alphabet:   Alphabet::Bytes,
+            coder:      CoderMode::Fixed(Coder::Huffman),
+            block_size: DEFAULT_BLOCK_SIZE,
+            streams:    1,
+            threads:    1,
+            dictionary: None,
+        }
+    }
+}
+
+/// What the compression spent the bytes of the .johnny file on.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CompressStats {
+    pub num_blocks:   usize,
+    /// The .johnny header and the block headers.
+    pub header_bytes: usize,
+    /// The alphabet data and the tables of the coders.
+    pub table_bytes:  usize,
+}
+
+/// Compresses the message to the bytes of a .johnny file.
+pub fn compress_buffer(buffer_in: &[u8], options: & CompressOptions) -> Vec<u8> {
+    compress_buffer_with_stats(buffer_in, options).0
+}
+
+/// Compresses the message, steps 1 to 6, and returns the bytes of the
+/// .johnny file and what they were spent on.
+pub fn compress_buffer_with_stats(buffer_in: &[u8], options: & CompressOptions) -> (Vec<u8>, CompressStats) {
+    let mut buffer_out: Vec<u8> = Vec::new();
+    let mut stats = CompressStats::default();
+
+    //    Apply the pre-filter to the buffer, the filter is written in the
+    //    .johnny header.
+    let filter = match options.filter {
+        FilterMode::Fixed(filter) => filter,
+        FilterMode::Auto          => filter::choose_filter(buffer_in),
+    };
+    progress!("...using filter {}", filter);
+    let buffer_filtered = filter.encode(buffer_in);
+
+    //    Split the message in blocks, each block is coded on it's own with
+    //    the alphabet and the coder, in auto mode the smallest coder.
+    //    The blocks are coded in options.threads threads and written in order.
+    let vec_blocks = split_blocks(buffer_filtered.len(), options.block_size, filter);
+    progress!("...using {} blocks in {} threads", vec_blocks.len(), options.threads);
+    let vec_coded = parallel::map_in_order(& vec_blocks, options.threads, |(block_start, block_end)| {
+        encode_block(& buffer_filtered[*block_start..*block_end], options)
+    });
+
+    let header = Header { filter, num_blocks: vec_blocks.len() as u32, checksum: crc32fast::hash(buffer_in) };
+    header.write_to_byte_buffer(& mut buffer_out);
+    stats.num_blocks = vec_blocks.len();
+    stats.header_bytes = HEADER_SIZE + vec_blocks.len() * BLOCK_HEADER_SIZE;
+    for ((block_start, block_end), coded) in vec_blocks.iter().zip(vec_coded) {
+        let block = & buffer_filtered[*block_start..*block_end];
+        progress!("...block of {} bytes using alphabet {} and coder {}, {} bytes", block.len(), coded.alphabet, coded.coder, coded.body.len());
+        let block_header = BlockHeader {
+            alphabet:  coded.alphabet,
+            coder:     coded.coder,
+            raw_len:   block.len() as u32,
+            coded_len: coded.body.len() as u32,
+        };
+        block_header.write_to_byte_buffer(& mut buffer_out);
+        buffer_out.extend_from_slice(& coded.body);
+        stats.table_bytes += coded.table_len;
+    }
+    (buffer_out, stats)
+}
+
+/// Decompresses the bytes of a .johnny file, steps 1 to 4, with the blocks
+/// decoded in threads threads.
+pub fn decompress_buffer(buffer_in: &[u8], threads: usize) -> Result<Vec<u8>> {
+    decompress_buffer_with_dictionary(buffer_in, threads, None)
+}
+
+/// Decompresses the bytes of a .johnny file, the blocks coded with a
+/// pretrained table need the dictionary.
+pub fn decompress_buffer_with_dictionary(buffer_in: &[u8], threads: usize, dictionary: Option<& Dictionary>) -> Result<Vec<u8>> {
+    decompress_buffer_with_limits(buffer_in, threads, dictionary, & Limits::NONE)
+}
+
+/// Decompresses the bytes of a .johnny file, if the message fits in the
+/// limits. The limits are checked with the block headers, before the blocks
+/// are decoded.
+pub fn decompress_buffer_with_limits(buffer_in: &[u8], threads: usize, dictionary: Option<& Dictionary>, limits: & Limits) -> Result<Vec<u8>> {
+    //    Read the .johnny header with the pre-filter that was used.
+    let header = Header::read_from_byte_buffer(buffer_in)?;
+    progress!("...using filter {}", header.filter);
+    progress!("...using {} blocks", header.num_blocks);
+
+    //    The index of the blocks, the block header and the position of
+    //    the body of each block.
+    let vec_index = read_block_index(buffer_in, & header)?;
+    let output_len: u64 = vec_index.iter().map(|(block_header, _block_start)| block_header.raw_len as u64).sum();
+    // Each thread has the symbols of one block at a time.
+    let max_symbols_len = vec_index.iter().map(|(block_header, _block_start)| symbols_len(block_header)).max().unwrap_or(0);
+    let symbols_len = max_symbols_len.saturating_mul(threads.clamp(1, vec_index.len().max(1)) as u64);
+    limits.check(buffer_in.len(), output_len, symbols_len)?;
+
+    // 2. and 3. Decode the symbols of each block, in threads threads,
+    //    and convert them back to bytes.
+    progress!("...decoding in {} threads", threads);
+    let vec_decoded = parallel::map_in_order(& vec_index, threads, |(block_header, block_start)| {
+        let block_end = block_start + block_header.coded_len as usize;
+        decode_block(& buffer_in[*block_start..block_end], block_header, dictionary)
+    });
+    let mut buffer_filtered: Vec<u8> = Vec::new();
+    for block in vec_decoded {
+        buffer_filtered.extend_from_slice(& block?);
+    }
+
+    // 4. Undo the pre-filter, and check the message.
+    let buffer_out = header.filter.decode(buffer_filtered);
+    let checksum = crc32fast::hash(& buffer_out);
+    if checksum != header.checksum {
+        return Err(Error::ChecksumMismatch { expected: header.checksum, actual: checksum });
+    }
+    Ok(buffer_out)
+}
+
+/// The number of bytes of the message of the .johnny file, from the block
+/// headers, without decoding the blocks.
+pub fn decompressed_size(buffer_in: &[u8]) -> Result<u64> {
+    let header = Header::read_from_byte_buffer(buffer_in)?;
+    let vec_index = read_block_index(buffer_in, & header)?;
+    Ok(vec_index.iter().map(|(block_header, _block_start)| block_header.raw_len as u64).sum())
+}
+
+/// Reads a Huffman mapping table, and builds it's decoding table, as in the
+/// body of a block. Returns the start of the coded data after the table.
+/// For the fuzzing of the table parser.
+#[doc(hidden)]
+pub fn parse_huffman_table(buffer_in: &[u8]) -> Result<usize> {
+    let mut map_table = MappingTable::new();
+    let header_2_start = map_table.read_mapping_table_from_byte_buffer(buffer_in)?;
+    let mut symbols: Vec<u8> = Vec::new();
+    map_table.decode_the_data(buffer_in, & mut symbols, header_2_start, buffer_in.len())?;
+    Ok(header_2_start)
+}
+
+/// The length of the Huffman code of each symbol with a count, for the
+/// tests of the code lengths.
+#[doc(hidden)]
+pub fn huffman_code_lengths(counts: &[usize]) -> Vec<(alphabet::Symbol, usize)> {
+    let mut map_table = MappingTable::new();
+    map_table.set_symbol_counts(counts);
+    map_table.generate_huffman_code();
+    map_table.code_lengths()
+}
+
+/// The header of each block and the position of it's body. The number of
+/// blocks comes from the file, each block is checked to be in the buffer
+/// before the next one is read.
+pub(crate) fn read_block_index(buffer_in: &[u8], header: & Header) -> Result<Vec<(BlockHeader, usize)>> {
+    let mut vec_index: Vec<(BlockHeader, usize)> = Vec::new();
+    let mut pos = HEADER_SIZE;
+    for _ in 0..header.num_blocks {
+        let block_header = BlockHeader::read_from_byte_buffer(& buffer_in[pos..])?;
+        progress!("...block of {} bytes using alphabet {} and coder {}", block_header.raw_len, block_header.alphabet, block_header.coder);
+        pos += BLOCK_HEADER_SIZE;
+        let block_end = pos + block_header.coded_len as usize;
+        if block_end > buffer_in.len() {
+            return Err(Error::PayloadUnderrun("a block is cut short"));
+        }
+        vec_index.push((block_header, pos));
+        pos = block_end;
+    }
+    Ok(vec_index)
+}
+
+/// The start and end of each block. With the shuffle filter the blocks also
+/// end at the end of each byte plane, so each plane gets it's own tables.
+fn split_blocks(len: usize, block_size: usize, filter: Filter) -> Vec<(usize, usize)> {
+    let mut vec_planes: Vec<(usize, usize)> = Vec::new();
+    match filter {
+        Filter::Shuffle(elem_size) if len >= elem_size as usize => {
+            // The bytes of the last incomplete element go with the last plane.
+            let plane_len = len / elem_size as usize;
+            for plane in 0..elem_size as usize {
+                let plane_end = if plane + 1 == elem_size as usize { len } else { (plane + 1) * plane_len };
+                vec_planes.push((plane * plane_len, plane_end));
+            }
+        }
+        _ => vec_planes.push((0, len)),
+    }
+
+    let mut vec_blocks: Vec<(usize, usize)> = Vec::new();
+    for (plane_start, plane_end) in vec_planes {
+        let mut block_start = plane_start;
+        while block_start < plane_end {
+            let block_end = plane_end.min(block_start + block_size);
+            vec_blocks.push((block_start, block_end));
+            block_start = block_end;
+        }
+    }
+    vec_blocks
+}
+
+/// A block coded with the alphabet and the coder, the alphabet is the bytes
+/// alphabet if the selected one has too many symbols for the coder.
+struct CodedBlock {
+    alphabet:  Alphabet,
+    coder:     Coder,
+    body:      Vec<u8>,
+    /// Bytes of the alphabet data and of the table of the coder in the body.
+    table_len: usize,
+}
+
+/// Codes one block with each of the coders of the mode and keeps the smallest.
+/// With a dictionary the block is coded with it's table, in auto mode it's
+/// one more candidate. If none of them is smaller than the block, it's stored.
+fn encode_block(block: &[u8], options: & CompressOptions) -> CodedBlock {
+    let mut vec_coders = options.coder.candidates();
+    let mut best: Option<CodedBlock> = None;
+    if let Some(dictionary) = & options.dictionary {
+        if options.coder != CoderMode::Auto {
+            vec_coders.clear();
+        }
+        match dictionary.encode_block(block, options.streams) {
+            Some(body) => best = Some(CodedBlock { alphabet: Alphabet::Bytes, coder: Coder::Dict, body, table_len: dictionary::ID_SIZE }),
+            None => {
+                // The bytes that aren't in the sample of the dictionary have no code.
+                progress!("...the block has bytes without a code in the dictionary, using it's own table");
+                if vec_coders.is_empty() {
+                    vec_coders.push(Coder::Huffman);
+                }
+            }
+        }
+    }
+    for coder in vec_coders {
+        let coded = encode_alphabet(block, options.alphabet, coder, options.streams);
+        let is_smaller = match & best {
+            Some(best_coded) => coded.body.len() < best_coded.body.len(),
+            None             => true,
+        };
+        if is_smaller {
+            best = Some(coded);
+        }
+    }
+    // A block that no coder makes smaller, a tiny block or random bytes, is
+    // stored as it is, the block header has it's length.
+    let best = best.unwrap();
+    if best.body.len() >= block.len() {
+        progress!("...the coded block isn't smaller than the {} bytes of the block, storing it", block.len());
+        return CodedBlock { alphabet: Alphabet::Bytes, coder: Coder::Stored, body: block.to_vec(), table_len: 0 };
+    }
+    best
+}
+
+/// Converts the block to the symbols of the alphabet, the alphabet data goes
+/// before the mapping table. The table and data positions are relative to the
+/// start of the table.
+fn encode_alphabet(block: &[u8], alphabet: Alphabet, coder: Coder, num_streams: usize) -> CodedBlock {
+    let mut buffer_body: Vec<u8> = Vec::new();
+    let table_len = match alphabet {
+        Alphabet::Bytes => encode_symbols(block, 256, coder, num_streams, & mut buffer_body),
+        Alphabet::U16   => {
+            let (symbols, alphabet_data) = alphabet::to_u16_symbols(block);
+            buffer_body.extend_from_slice(& alphabet_data);
+            encode_symbols(& symbols, 0x1_0000, coder, num_streams, & mut buffer_body)
+                .map(|table_len| alphabet_data.len() + table_len)
+        }
+        Alphabet::Pairs => {
+            let (symbols, alphabet_size, alphabet_data) = alphabet::to_pair_symbols(block);
+            buffer_body.extend_from_slice(& alphabet_data);
+            encode_symbols(& symbols, alphabet_size, coder, num_streams, & mut buffer_body)
+                .map(|table_len| alphabet_data.len() + table_len)
+        }
+        Alphabet::Words => {
+            let (symbols, alphabet_size, alphabet_data) = alphabet::to_word_symbols(block);
+            buffer_body.extend_from_slice(& alphabet_data);
+            encode_symbols(& symbols, alphabet_size, coder, num_streams, & mut buffer_body)
+                .map(|table_len| alphabet_data.len() + table_len)
+        }
+    };
+    if let Some(table_len) = table_len {
+        return CodedBlock { alphabet, coder, body: buffer_body, table_len };
+    }
+    progress!("...the {} alphabet has too many symbols for the {} coder, using the bytes alphabet", alphabet, coder);
+    buffer_body.clear();
+    let table_len = encode_symbols(block, 256, coder, num_streams, & mut buffer_body)
+        .expect("...every coder has room for the 256 bytes.");
+    CodedBlock { alphabet: Alphabet::Bytes, coder, body: buffer_body, table_len }
+}
+
+/// Steps 2 to 6 of the compression, for the symbols of any alphabet.
+/// Returns the number of bytes of the table, or None if the alphabet has too
+/// many symbols for the range coder or the tANS coder.
+fn encode_symbols<S: SymbolType>(symbols: &[S], alphabet_size: usize, coder: Coder, num_streams: usize, buffer_out: & mut Vec<u8>) -> Option<usize> {
+
+    // 2. Determine the frequency of the symbols (different bytes) in the input buffer.
+    let mut map_table = MappingTable::new();
+    map_table.print_text_char = alphabet_size <= 256;
+    map_table.get_buffer_byte_symbols_freq(symbols, alphabet_size);
+
+    //    The range coder and the tANS coder use the same frequencies, in place
+    //    of steps 3 to 6.
+    match coder {
+        Coder::Arith   => return arith::encode_the_data(& map_table.get_symbol_counts(), symbols, buffer_out),
+        Coder::Fse     => return fse::encode_the_data(& map_table.get_symbol_counts(), symbols, buffer_out),
+        Coder::Huffman => (),
+        Coder::Dict    => unreachable!("...the dictionary codes the block without the alphabet."),
+        Coder::Stored  => unreachable!("...a stored block isn't coded."),
+    }
+        
+    // 3. By using a priority queue and the Huffman coding tree find the best    
+    //    coding for each symbol of the message. Create a table for the code.
+    //    This table inverted will also have to be known in the decoding phase.
+    map_table.generate_huffman_code();
+
+    // 4. Write the table to the beginning of byte buffer and the 32 bit heading,
+    //    with the start of the data.
+    let table_start = buffer_out.len();
+    let mut buffer_table: Vec<u8> = Vec::new();
+    map_table.write_mapping_table_to_byte_buffer(& mut buffer_table);
+    buffer_out.extend_from_slice(& buffer_table);
+    debug_assert_eq!(buffer_out.len() - table_start, map_table.mapping_table_size());
+
+    // 5. With the new dictionary, encode the message in bytes to a byte buffer.
+    // 6. Write the first 8 byte with an usize 64 bit's representing the number
+    //    of bytes or total symbols in the original file of the message. 
+    map_table.encode_the_data(symbols, num_streams, buffer_out);
+    Some(map_table.mapping_table_size())
+}
+
+/// Bytes of the symbols of a block while it's decoded, at most one symbol
+/// for each byte of the block. The symbols of the bytes alphabet are the
+/// decoded block itself.
+pub(crate) fn symbols_len(block_header: & BlockHeader) -> u64 {
+    let raw_len = block_header.raw_len as u64;
+    match block_header.alphabet {
+        _ if block_header.coder == Coder::Stored || block_header.coder == Coder::Dict => 0,
+        Alphabet::Bytes => 0,
+        Alphabet::U16   => raw_len,
+        Alphabet::Pairs => raw_len * 2,
+        Alphabet::Words => raw_len * 4,
+    }
+}
+
+/// Steps 2 and 3 of the decompression for one block, it must decode to the
+/// number of bytes of the block header.
+pub(crate) fn decode_block(buffer_body: &[u8], block_header: & BlockHeader, dictionary: Option<& Dictionary>) -> Result<Vec<u8>> {
+    let raw_len = block_header.raw_len as usize;
+    let coder = block_header.coder;
+    let buffer_out = match block_header.alphabet {
+        _ if coder == Coder::Stored => buffer_body.to_vec(),
+        _ if coder == Coder::Dict => {
+            let dictionary = match dictionary {
+                Some(dictionary) => dictionary,
+                None => return Err(Error::Dictionary { needed: dictionary::read_id(buffer_body)?, given: None }),
+            };
+            dictionary.decode_block(buffer_body, raw_len)?
+        }
+        Alphabet::Bytes => decode_symbols::<u8>(buffer_body, coder, true, raw_len)?,
+        Alphabet::U16   => {
+            let (last_byte, data_len) = alphabet::read_u16_alphabet_data(buffer_body)?;
+            let symbols = decode_symbols::<u16>(& buffer_body[data_len..], coder, false, raw_len / 2)?;
+            alphabet::from_u16_symbols(& symbols, last_byte)
+        }
+        Alphabet::Pairs => {
+            let (vec_pairs, data_len) = alphabet::read_pairs_alphabet_data(buffer_body)?;
+            let symbols = decode_symbols::<u16>(& buffer_body[data_len..], coder, false, raw_len)?;
+            alphabet::from_pair_symbols(& symbols, & vec_pairs, raw_len)?
+        }
+        Alphabet::Words => {
+            let (vec_words, data_len) = alphabet::read_words_alphabet_data(buffer_body, raw_len)?;
+            let symbols = decode_symbols::<u32>(& buffer_body[data_len..], coder, false, raw_len)?;
+            alphabet::from_word_symbols(& symbols, & vec_words, raw_len)?
+        }
+    };
+    if buffer_out.len() != raw_len {
+        return Err(Error::PayloadUnderrun("a block decodes to another number of bytes than it's header"));
+    }
+    Ok(buffer_out)
+}
+
+/// Steps 2 and 3 of the decompression, for the symbols of any alphabet,
+/// at most max_symbols symbols.
+fn decode_symbols<S: SymbolType>(buffer_body: &[u8], coder: Coder, print_text_char: bool, max_symbols: usize) -> Result<Vec<S>> {
+
+    let mut symbols: Vec<S> = Vec::new();
+    match coder {
+        Coder::Arith => {
+            arith::decode_the_data(buffer_body, & mut symbols, max_symbols)?;
+            return Ok(symbols);
+        }
+        Coder::Fse => {
+            fse::decode_the_data(buffer_body, & mut symbols, max_symbols)?;
+            return Ok(symbols);
+        }
+        Coder::Huffman => (),
+        Coder::Dict    => unreachable!("...the dictionary decodes the block without the alphabet."),
+        Coder::Stored  => unreachable!("...a stored block isn't decoded."),
+    }
+
+    // 2. Extract the symbols coding table to an internal representation. That is
+    //    the one with the Huffman coding inverted for decoding. 
+    let mut map_table = MappingTable::new();
+    map_table.print_text_char = print_text_char;
+    let header_2_start = map_table.read_mapping_table_from_byte_buffer(buffer_body)?;
+
+    // 3. Read the 32 bit header with the index (of the byte) of the start of
+    //    the data in the .johnny file. Read the second header with the number
+    //    of original symbols, or we could say original bytes. 
+    //    Apply the decoding table to the coded message bytes, buffer_in, and decode or
+    //    decompress it into a binary buffer_out. 
+    map_table.decode_the_data(buffer_body, & mut symbols, header_2_start, max_symbols)?;
+    Ok(symbols)
+}
@@ -0,0 +1,66 @@
+// The limits of the decompression, for the .johnny files of untrusted sources.
+//
+// The block headers have the number of bytes of each block, and a few bytes
+// of a run or of the range coder can decode to gigabytes, so a tiny crafted
+// file can claim a huge message. The limits are checked with the sizes of
+// the block headers, before any block is decoded. Each decoder decodes at
+// most as many symbols as the block header has bytes, and the alphabets
+// convert them back to at most that number of bytes, a pair or a word of
+// the dictionary can't go past it, so the limits also hold during the
+// decoding. The symbols of the u16, pairs and words alphabets take 2 or 4
+// bytes each, their buffers are counted in the memory.
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// Maximum number of bytes of the decompressed message.
+    pub max_output: u64,
+    /// Maximum number of bytes of the message for each byte of the .johnny file.
+    pub max_ratio:  u64,
+    /// Maximum number of bytes of the buffers of the decompression, the
+    /// .johnny file, the symbols of the blocks being decoded, the decoded
+    /// blocks and the message.
+    pub max_memory: u64,
+}
+
+impl Limits {
+    /// No limits, the default of the library and of the command line.
+    pub const NONE: Limits = Limits { max_output: u64::MAX, max_ratio: u64::MAX, max_memory: u64::MAX };
+
+    /// Checks the size of the message that a .johnny file of input_len bytes
+    /// decodes to, with symbols_len bytes of symbols in memory at the same
+    /// time.
+    pub fn check(&self, input_len: usize, output_len: u64, symbols_len: u64) -> Result<()> {
+        if output_len > self.max_output {
+            return Err(Error::LimitExceeded { limit: "output size", value: output_len, max: self.max_output });
+        }
+        let input_len = input_len as u64;
+        if output_len > input_len.saturating_mul(self.max_ratio) {
+            let ratio = output_len.div_ceil(input_len.max(1));
+            return Err(Error::LimitExceeded { limit: "expansion ratio", value: ratio, max: self.max_ratio });
+        }
+        // The decoded blocks and the message are in memory at the same time,
+        // and the symbols while the last blocks are decoded.
+        let memory = input_len.saturating_add(output_len.saturating_mul(2)).saturating_add(symbols_len);
+        if memory > self.max_memory {
+            return Err(Error::LimitExceeded { limit: "memory", value: memory, max: self.max_memory });
+        }
+        Ok(())
+    }
+
+    /// Checks the bytes of memory that a single step of the decompression
+    /// asks for, before they are allocated.
+    pub fn check_memory(&self, memory: u64) -> Result<()> {
+        if memory > self.max_memory {
+            return Err(Error::LimitExceeded { limit: "memory", value: memory, max: self.max_memory });
+        }
+        Ok(())
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits::NONE
+    }
+}
@@ -21,9 +21,62 @@
 * to compress a text or binary file do:                                       *
 * huffman_codes compress input_text.txt                                       *
 *                                                                             *
+* to compress numeric or sensor data with a pre-filter do:                    *
+* huffman_codes compress --filter delta:2 input_samples.raw                   *
+*   filters: none, delta, delta:N, shuffle:N, x86, arm64, auto                *
+*                                                                             *
+* to compress with a larger alphabet (16 bit words or byte pairs) do:         *
+* huffman_codes compress --alphabet u16 input_utf16.txt                       *
+*   alphabets: bytes, u16, pairs, words                                       *
+*                                                                             *
+* to compress with the range coder (arithmetic coding) do:                    *
+* huffman_codes compress --coder arith input_skewed.bin                       *
+*   coders: huffman, arith, fse, auto                                         *
+*                                                                             *
+* to choose the coder for each block of 1 MiB do:                             *
+* huffman_codes compress --coder auto --block-size 1M input_mixed.bin         *
+*                                                                             *
+* to split the Huffman codes in 4 interleaved streams, for faster decoding:   *
+* huffman_codes compress --streams 4 input_big.bin                            *
+*                                                                             *
+* to compress or decompress the blocks in 8 threads (0 for all the cores) do: *
+* huffman_codes compress -T 8 input_big.bin                                   *
+*                                                                             *
+* to train a Huffman table on a sample of many small messages, and to         *
+* compress and decompress them with the table of the dictionary file do:      *
+* huffman_codes train --table dict.jtbl sample_records.bin                    *
+* huffman_codes compress --table dict.jtbl record.bin                         *
+* huffman_codes decompress --table dict.jtbl record.bin.johnny                *
+*                                                                             *
 * to decompress a compressed text or binary file do:                          *
 * huffman_codes decompress output_text.txt                                    *
 *                                                                             *
+* to decompress an untrusted file, with limits on the size of the message,    *
+* on the expansion ratio and on the memory do:                                *
+* huffman_codes decompress --max-output 1G --max-ratio 1000 --max-memory 2G   *
+*                          upload.johnny                                      *
+*                                                                             *
+* to encrypt and authenticate the .johnny file with a password (Argon2id      *
+* and ChaCha20-Poly1305), or with a key of 32 bytes, and to decrypt it do:    *
+* huffman_codes compress --password-file secret.txt bundle.conf               *
+* huffman_codes decompress --password-file secret.txt bundle.conf.johnny      *
+* huffman_codes compress --key-file device.key bundle.conf                    *
+*                                                                             *
+* to make an Ed25519 key pair, to sign a .johnny file with the signing key    *
+* and to verify it with the verifying key do:                                 *
+* huffman_codes keygen signing.key                                            *
+* huffman_codes sign --key-file signing.key bundle.conf.johnny                *
+* huffman_codes verify --key-file signing.key.pub bundle.conf.johnny          *
+*                                                                             *
+* to add a recovery record with 10% of Reed-Solomon parity to an archive,     *
+* and to repair it when it's damaged, do:                                     *
+* huffman_codes compress --recovery 10 archive.tar                            *
+* huffman_codes repair archive.tar.johnny                                     *
+*                                                                             *
+* to benchmark the options on generated corpora of 8 MiB do:                  *
+* huffman_codes bench --coder auto --size 8M                                  *
+*   corpora: text, random, skewed, zeros, executable                          *
+*                                                                             *
 *                                                                             *
 * Algorithm:                                                                  *
 * 1. First we will read the parameters, validate them and decide if we will   *
@@ -33,98 +86,149 @@
 * Function compress:                                                          *
 * 1. Read all of the input file in binary buffer. So we have a one byte       *
 *    representation of each symbol, this step will make the problem.          *
+*    The file is memory mapped when it's a regular file, unless --no-mmap.    *
+*    A mapped file that is truncated while it's read stops the program        *
+*    with a SIGBUS.                                                           *
+*    Apply the pre-filter to the buffer, the filter is written in the         *
+*    .johnny header.                                                          *
+*    Split the buffer in blocks, each block has it's own alphabet, coder      *
+*    and tables.                                                              *
+*    Convert the block to the symbols of the alphabet, bytes, 16 bit          *
+*    words or bytes and byte pairs.                                           *
 * 2. Determine the frequency of the symbols (different bytes) in the input    *
 *    buffer.                                                                  *
 * 3. By using a priority queue and the Huffman coding tree find the best      *    
 *    coding for each symbol of the message. Create a table for the code.      *
 *    This table inverted will also have to be known in the decoding phase.    *
-* 4. Write the table to the beginning of byte buffer and 16 bit header,       *
-*    with the start of the data in the buffer_out.                            *
+* 4. Write the table to the beginning of byte buffer and 32 bit header,       *
+*    with the start of the data in the buffer_out. The table only has the     *
+*    length of the code of each symbol, the codes are canonical.              *
+*    A block with a single symbol is a run, it's code is empty and the        *
+*    block only has the number of symbols of step 6, so it's size is          *
+*    constant per block, a longer run has more blocks.                        *
 * 5. With the new dictionary, encode the message in bytes to a byte buffer.   *
 * 6. Write the first 8 byte with an usize 64 bit's representing the number    *
 *    of bytes or total symbols in the original file of the message.           *
+*    The range coder and the tANS coder use the same frequencies in place     *
+*    of the steps 3 to 6.                                                     *
 * 7. Write the final compressed byte buffer to file .johnny .                 *
+*    With a password or a key, all of the .johnny file is encrypted first.    *
+*    With --recovery the parity of the file is appended to it.                *
 *                                                                             *
 *                                                                             *
 * Function decompress:                                                        *
 * 1. Read the file from disk into a byte buffer in binary representation.     *
+*    With a password or a key, the file is authenticated and decrypted,       *
+*    nothing is decoded if the tag of the encryption isn't right.             *
+*    Read the .johnny header with the pre-filter that was used, and then      *
+*    the steps 2 and 3 for each block.                                        *
 * 2. Extract the symbols coding table to an internal representation. That is, *
 *    the one with the Huffman coding inverted for decoding.                   *
-* 3. Read the 16 bit header with the index (of the byte) of the start of      *
+* 3. Read the 32 bit header with the index (of the byte) of the start of      *
 *    the data in the .johnny file. Read the second header with the number     *
 *    of original symbols, or we could say original bytes.                     *
 *    Apply the decoding table to the coded message bytes, buffer_in, and      *
 *    decode or decompress it into a binary buffer_out.                        *
-* 4. Write to the output file of the decoded binary or text data.             *
+* 4. Undo the pre-filter, check the CRC32 of the message with the one of      *
+*    the .johnny header and write to the output file of the decoded binary    *
+*    or text data.                                                            *
+*                                                                             *
+* The errors of a corrupt file or of the arguments are printed, and the       *
+* program exits with the code of the error, see the README.                   *
+* The output file is written to a temporary file and renamed into place,      *
+* so a crash or a Ctrl-C never leaves a truncated file.                       *
 *                                                                             *
 *                                                                             *
 * License: MIT Open Source                                                    *
 *                                                                             *
 * Time:                                                                       *
 *                                                                             *
-*   [ in SSD, std HasMap]                                                     *
-*   compress    3.4 MB -> 2.4 MB executable in to .johnny in 0.211 s          *
-*   decompress  2.4 MB -> 3.4 MB .johnny in to executable in 0.521 s          *
-*                                                                             *
-*   [ in SSD, fast HashBrown HashMap and string as bytes ]                    *
-*   compress    3.4 MB -> 2.4 MB executable in to .johnny in 0.061 s          *
-*   decompress  2.4 MB -> 3.4 MB .johnny in to executable in 0.218 s          *
-*   NOTE: Current version!                                                    *
-*                                                                             *
-*   [ in RAM /dev/shm/, std HashMap ]                                         *
-*   compress    600 MB -> 600MB + 2570 Bytes mp4 in to .johnny in 33.825 s    *
-*   decompress  600MB + 2570 Bytes -> 600 MB .johnny in to mp4 in 1m 44.326 s *
-*                                                                             *
-*   [ in RAM /dev/shm/, fast HashBrown HashMap ]                              *
-*   compress    600 MB -> 600MB + 2570 Bytes mp4 in to .johnny in 23.863 s    *
-*   decompress  600MB + 2570 Bytes -> 600 MB .johnny in to mp4 in 51.689 s    *
-*                                                                             *
-*   [ in RAM /dev/shm/, fast HashBrown HashMap and string as bytes ]          *
-*   compress    600 MB -> 600MB + 2570 Bytes mp4 in to .johnny in 8.574 s     *
-*   decompress  600MB + 2570 Bytes -> 600 MB .johnny in to mp4 in 51.689 s    *
-*   NOTE: Current version!                                                    *
+*   To measure the speed, the ratio and the bytes of the tables on the same   *
+*   generated corpora in any machine do:                                      *
+*   huffman_codes bench                                                       *
+*   and for the statistics of criterion:                                      *
+*   cargo bench                                                               *
 *                                                                             *
 ******************************************************************************/
 
-//use std::collections::HashMap;
-
-// The fastest HashMap for Rust. HashBrown a drop in replacement for std HashMap.
-use hashbrown::HashMap;
-
 use std::env;
-use std::io::{Read, Write};
+use std::io::{self, Read};
 use std::process;
 use std::path::Path;
 use std::fs::File;
 // use std::fs::Metadata;
 // use std::io::Read;
-use std::io::BufReader;  // Faster :-D
-use std::io::BufWriter;  // Faster :-D
 use std::ffi::OsStr;
-// use priority_queue::PriorityQueue;      // for Huffman code algorithm.
-// use priority_queue_rs::PriorityQueue;   // for Huffman code algorithm.
+use std::time::Instant;
+
+use huffman_codes::{corpus, parallel, streams, CompressOptions, CompressStats};
+use huffman_codes::alphabet::Alphabet;
+use huffman_codes::coder::CoderMode;
+use huffman_codes::corpus::Corpus;
+use huffman_codes::dictionary::Dictionary;
+use huffman_codes::encryption::{self, Secret};
+use huffman_codes::error::{Error, Result};
+use huffman_codes::filter::FilterMode;
+use huffman_codes::limits::Limits;
+use huffman_codes::recovery;
+use huffman_codes::signature;
+
+mod input;
+mod output;
+
+use input::{FileStamp, Input};
 
-/// Usage: "huffman_codes [compress|decompress] filename"
-static USAGE: &str = "   Usage: \"huffman_codes [compress|decompress] filename";
+/// Usage: "huffman_codes [compress|decompress|train|bench|keygen|sign|verify|repair] [--filter name] [--alphabet name] [--coder name] [--block-size N] [--streams N] [-T N] [--table dict.jtbl] [--password-file FILE] [--key-file FILE] [--recovery N] [--max-output N] [--max-ratio N] [--max-memory N] [--no-mmap] [--size N] filename"
+static USAGE: &str = "   Usage: \"huffman_codes [compress|decompress] [--filter none|delta[:N]|shuffle:N|x86|arm64|auto] [--alphabet bytes|u16|pairs|words] [--coder huffman|arith|fse|auto] [--block-size N[K|M]] [--streams N] [-T|--threads N] [--table dict.jtbl] [--password-file FILE|--key-file FILE] [--recovery N] [--no-mmap] filename\"\n          \"huffman_codes decompress [-T|--threads N] [--table dict.jtbl] [--password-file FILE|--key-file FILE] [--max-output N[K|M|G]] [--max-ratio N] [--max-memory N[K|M|G]] filename.johnny\"\n          \"huffman_codes train [--table dict.jtbl] sample_corpus\"\n          \"huffman_codes keygen signing.key\"\n          \"huffman_codes sign --key-file signing.key filename.johnny\"\n          \"huffman_codes verify --key-file signing.key.pub filename.johnny\"\n          \"huffman_codes repair [--table dict.jtbl] [--max-output N[K|M|G]] [--max-ratio N] [--max-memory N[K|M|G]] filename.johnny\"\n          \"huffman_codes bench [options] [--size N[K|M]]\"";
+
+/// The default size of each corpus of the benchmark.
+const DEFAULT_BENCH_SIZE: usize = 8 * 1024 * 1024;
+
+/// The benchmark keeps the fastest of the runs.
+const BENCH_RUNS: usize = 3;
 
 fn main() {
     println!("***********************************************************");
     println!("** Huffman codes - compress and decompress .johnny files **");
     println!("***********************************************************");
+    output::remove_temp_files_on_interrupt();
     let args: Vec<String> = env::args().collect();
-    let cfg = Config::new(& args);
+    if let Err(error) = run(& args) {
+        eprintln!(" Error: {}", error);
+        if let Error::Usage(_) = error {
+            println!("{}", USAGE);
+        }
+        process::exit(error.exit_code());
+    }
+    println!("...ended processing the file.");
+}
+
+/// Parses the command line and runs the action.
+fn run(args: &[String]) -> Result<()> {
+    let cfg = Config::new(args)?;
     match cfg.action {
         Action::Compress   => compress( & cfg ),
         Action::Decompress => decompress( & cfg ),
+        Action::Train      => train( & cfg ),
+        Action::Bench      => bench( & cfg ),
+        Action::Keygen     => keygen( & cfg ),
+        Action::Sign       => sign( & cfg ),
+        Action::Verify     => verify( & cfg ),
+        Action::Repair     => repair( & cfg ),
     }
-    println!("...ended processing the file.");
 }
 
 #[derive(Debug)]
 #[derive(PartialEq)]
 enum Action {
     Compress,
-    Decompress
+    Decompress,
+    Train,
+    Bench,
+    Keygen,
+    Sign,
+    Verify,
+    Repair,
 }
 
 /// Configuration structure to parse the command line options.  
@@ -132,621 +236,488 @@ enum Action {
 struct Config {
     action: Action,
     filename: String,
+    options: CompressOptions,
+    table_filename: Option<String>,
+    secret: Option<Secret>,
+    recovery_percent: usize,
+    limits: Limits,
+    no_mmap: bool,
+    bench_size: usize,
 }
 
 impl Config {
     /// Constructor - Is were the parsing is made.
-    /// It exists if an error occurs.
-    fn new(args: &[String]) -> Config {
-        if args.len() != 3 {
-            println!(" Invalid or insufficient parameters...");
-            println!("{}", USAGE);
-            process::exit(0)
+    /// It returns a usage error if the arguments aren't valid.
+    fn new(args: &[String]) -> Result<Config> {
+        if args.len() < 2 {
+            return Err(Error::Usage("Invalid or insufficient parameters...".to_string()));
         }
         // casting your String into an &str (a string slice)
         let action = match &( args[1].to_ascii_uppercase() )[..] {
             "COMPRESS"   => Action::Compress,
             "DECOMPRESS" => Action::Decompress,  
-            _ => {    
-                println!(" Invalid compress or decompress action ex: huffman_codes compress  ...");
-                println!("{}", USAGE);
-                process::exit(0)
-            } 
+            "TRAIN"      => Action::Train,
+            "BENCH"      => Action::Bench,
+            "KEYGEN"     => Action::Keygen,
+            "SIGN"       => Action::Sign,
+            "VERIFY"     => Action::Verify,
+            "REPAIR"     => Action::Repair,
+            _ => return Err(Error::Usage("Invalid compress or decompress action ex: huffman_codes compress  ...".to_string())),
         };
 
-        let filename: String = args[2].to_string();
-        // Validate if filename exists.
+        if action != Action::Bench && args.len() < 3 {
+            return Err(Error::Usage("Invalid or insufficient parameters...".to_string()));
+        }
+
+        // The options are between the action and the filename, the benchmark
+        // has no filename.
+        let options_end = if action == Action::Bench { args.len() } else { args.len() - 1 };
+        let mut options = CompressOptions::default();
+        let mut table_filename: Option<String> = None;
+        let mut secret_filename: Option<(&str, String)> = None;
+        let mut recovery_percent = 0;
+        let mut limits = Limits::NONE;
+        let mut no_mmap = false;
+        let mut bench_size = DEFAULT_BENCH_SIZE;
+        let mut i = 2;
+        while i < options_end {
+            match &args[i][..] {
+                "--filter" if i + 1 < options_end => {
+                    i += 1;
+                    options.filter = match FilterMode::parse(&args[i]) {
+                        Some(f) => f,
+                        None => return Err(Error::Usage(format!("Invalid filter '{}' ex: none, delta, delta:2, shuffle:4, x86, arm64 or auto", args[i]))),
+                    };
+                }
+                "--alphabet" if i + 1 < options_end => {
+                    i += 1;
+                    options.alphabet = match Alphabet::parse(&args[i]) {
+                        Some(a) => a,
+                        None => return Err(Error::Usage(format!("Invalid alphabet '{}' ex: bytes, u16, pairs or words", args[i]))),
+                    };
+                }
+                "--coder" if i + 1 < options_end => {
+                    i += 1;
+                    options.coder = match CoderMode::parse(&args[i]) {
+                        Some(c) => c,
+                        None => return Err(Error::Usage(format!("Invalid coder '{}' ex: huffman, arith, fse or auto", args[i]))),
+                    };
+                }
+                "--block-size" if i + 1 < options_end => {
+                    i += 1;
+                    options.block_size = match parse_size(&args[i]) {
+                        Some(size) if size > 0 && size <= u32::MAX as usize => size,
+                        _ => return Err(Error::Usage(format!("Invalid block size '{}' ex: 65536, 64K or 4M", args[i]))),
+                    };
+                }
+                "--streams" if i + 1 < options_end => {
+                    i += 1;
+                    options.streams = match args[i].parse::<usize>() {
+                        Ok(n) if (1..=streams::MAX_STREAMS).contains(&n) => n,
+                        _ => return Err(Error::Usage(format!("Invalid number of streams '{}' ex: 1, 2, 4 or 8, up to {}", args[i], streams::MAX_STREAMS))),
+                    };
+                }
+                "-T" | "--threads" if i + 1 < options_end => {
+                    i += 1;
+                    options.threads = match args[i].parse::<usize>() {
+                        Ok(n) => parallel::num_threads(n),
+                        Err(_) => return Err(Error::Usage(format!("Invalid number of threads '{}' ex: 1, 8 or 0 for all the cores", args[i]))),
+                    };
+                }
+                "--table" if i + 1 < options_end => {
+                    i += 1;
+                    table_filename = Some(args[i].clone());
+                }
+                "--password-file" | "--key-file" if i + 1 < options_end => {
+                    if secret_filename.is_some() {
+                        return Err(Error::Usage("Use only one of --password-file and --key-file".to_string()));
+                    }
+                    secret_filename = Some((&args[i][..], args[i + 1].clone()));
+                    i += 1;
+                }
+                "--recovery" if i + 1 < options_end => {
+                    i += 1;
+                    recovery_percent = match args[i].trim_end_matches('%').parse::<usize>() {
+                        Ok(percent) if (1..=100).contains(&percent) => percent,
+                        _ => return Err(Error::Usage(format!("Invalid recovery record '{}' ex: 5 or 10, the percent of parity from 1 to 100", args[i]))),
+                    };
+                }
+                "--max-output" if i + 1 < options_end => {
+                    i += 1;
+                    limits.max_output = match parse_size(&args[i]) {
+                        Some(size) => size as u64,
+                        None => return Err(Error::Usage(format!("Invalid maximum output size '{}' ex: 100M or 2G", args[i]))),
+                    };
+                }
+                "--max-ratio" if i + 1 < options_end => {
+                    i += 1;
+                    limits.max_ratio = match args[i].parse::<u64>() {
+                        Ok(ratio) if ratio > 0 => ratio,
+                        _ => return Err(Error::Usage(format!("Invalid maximum expansion ratio '{}' ex: 100 or 1000", args[i]))),
+                    };
+                }
+                "--max-memory" if i + 1 < options_end => {
+                    i += 1;
+                    limits.max_memory = match parse_size(&args[i]) {
+                        Some(size) => size as u64,
+                        None => return Err(Error::Usage(format!("Invalid maximum memory '{}' ex: 512M or 4G", args[i]))),
+                    };
+                }
+                "--no-mmap" => no_mmap = true,
+                "--size" if i + 1 < options_end => {
+                    i += 1;
+                    bench_size = match parse_size(&args[i]) {
+                        Some(size) if size > 0 => size,
+                        _ => return Err(Error::Usage(format!("Invalid benchmark size '{}' ex: 1M or 64M", args[i]))),
+                    };
+                }
+                _ => return Err(Error::Usage(format!("Invalid or incomplete option '{}'", args[i]))),
+            }
+            i += 1;
+        }
+
+        if action == Action::Bench {
+            return Ok(Config { action, filename: String::new(), options, table_filename, secret: None, recovery_percent, limits, no_mmap, bench_size });
+        }
+
+        let filename: String = args[args.len() - 1].to_string();
         let file_path = Path::new( &filename );
+
+        // The keygen action writes the signing key file, it's never replaced,
+        // the file is created new.
+        if action == Action::Keygen {
+            return Ok(Config { action, filename, options, table_filename, secret: None, recovery_percent, limits, no_mmap, bench_size });
+        }
+
+        // Validate if filename exists.
         if !( file_path.exists() ) {
-            println!(" Invalid or not existing filename '{}'", filename);
-            println!("{}", USAGE);
-            process::exit(0)
+            return Err(Error::Usage(format!("Invalid or not existing filename '{}'", filename)));
         }
 
-        if action == Action::Decompress { 
-            // If is Action.decompress, validates if it ends with a .johnny extension :-D hehehehe!                      
+        if action == Action::Decompress || action == Action::Repair { 
+            // If is Action.decompress or Action.repair, validates if it ends with a .johnny extension :-D hehehehe!                      
             let flag_error_in_extension = match file_path.extension().and_then(OsStr::to_str) {
-                    Some(s) => if s.to_lowercase() == ("johnny") {
-                                        false
-                                    } else {
-                                        true
-                                    },
-                    None         => true,  
+                    Some(s) => s.to_lowercase() != "johnny",
+                    None    => true,
                 };
             
             if flag_error_in_extension {
-                println!(" Can't decompress a file without the extension .johnny ... '{}'", filename);
-                println!("{}", USAGE);
-                process::exit(0)
+                return Err(Error::Usage(format!("Can't decompress a file without the extension .johnny ... '{}'", filename)));
             }
         }
         
-        Config { action, filename }
+        // The train action writes the dictionary, compress and decompress read it.
+        if action != Action::Train {
+            if let Some(table_filename) = & table_filename {
+                options.dictionary = Some(Dictionary::from_bytes(& get_file_as_byte_vec(table_filename)?)?);
+            }
+        }
+
+        // Compress encrypts the .johnny file, decompress decrypts it.
+        let secret = match secret_filename {
+            Some((option, secret_filename)) => Some(read_secret(option, & secret_filename)?),
+            None => None,
+        };
+
+        // Sign and verify read the signing key or the verifying key.
+        if (action == Action::Sign || action == Action::Verify) && !matches!(secret, Some(Secret::Key(_))) {
+            return Err(Error::Usage("Sign and verify need the key file, ex: --key-file signing.key or --key-file signing.key.pub".to_string()));
+        }
+
+        Ok(Config { action, filename, options, table_filename, secret, recovery_percent, limits, no_mmap, bench_size })
+    }
+}
+
+/// The password of a password file, without the line break at the end, or
+/// the key of a key file.
+fn read_secret(option: &str, secret_filename: &str) -> Result<Secret> {
+    let bytes = get_file_as_byte_vec(secret_filename)?;
+    if option == "--key-file" {
+        return Secret::key_from_bytes(& bytes).ok_or_else(||
+            Error::Usage(format!("Invalid key file '{}' ex: 32 bytes or 64 hexadecimal digits", secret_filename)));
+    }
+    let mut password = bytes;
+    while password.last() == Some(& b'\n') || password.last() == Some(& b'\r') {
+        password.pop();
+    }
+    if password.is_empty() {
+        return Err(Error::Usage(format!("Empty password file '{}'", secret_filename)));
     }
+    Ok(Secret::Password(password))
 }
 
-fn compress(cfg: & Config) {
+/// Parses a size in bytes, with an optional K, M or G suffix, ex: "64K".
+fn parse_size(text: &str) -> Option<usize> {
+    let text = text.to_ascii_uppercase();
+    let (number, multiplier) = match text.as_bytes().last() {
+        Some(b'K') => (&text[..text.len() - 1], 1024),
+        Some(b'M') => (&text[..text.len() - 1], 1024 * 1024),
+        Some(b'G') => (&text[..text.len() - 1], 1024 * 1024 * 1024),
+        _          => (&text[..], 1),
+    };
+    number.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+fn compress(cfg: & Config) -> Result<()> {
     println!("...start compressing file {}", cfg.filename);
 
     // 1. Read all of the input file in binary buffer. So we have a one byte
     //    representation of each symbol, this step will make the problem.
-    let buffer_in: Vec<u8> = get_file_as_byte_vec( &cfg.filename );
-    let mut buffer_out: Vec<u8> = Vec::new();
-
-    if buffer_in.len() <= 2 {
-        buffer_out = buffer_in;
-    } else {
-
-        // 2. Determine the frequency of the symbols (different bytes) in the input buffer.
-        let mut map_table = MappingTable::new();
-        map_table.get_buffer_byte_symbols_freq(& buffer_in);
-            
-        // 3. By using a priority queue and the Huffman coding tree find the best    
-        //    coding for each symbol of the message. Create a table for the code.
-        //    This table inverted will also have to be known in the decoding phase.
-        map_table.generate_huffman_code();
-
-        // 4. Write the table to the beginning of byte buffer and the 16 bit heading,
-        //    with the start of the data.
-        map_table.write_mapping_table_to_byte_buffer(& mut buffer_out);
-
-        // 5. With the new dictionary, encode the message in bytes to a byte buffer.
-        // 6. Write the first 8 byte with an usize 64 bit's representing the number
-        //    of bytes or total symbols in the original file of the message. 
-        map_table.encode_the_data(& buffer_in, & mut buffer_out);
+    let stamp_before = input::file_stamp(& cfg.filename);
+    let buffer_in: Input = get_file_as_input( &cfg.filename, cfg.no_mmap )?;
+
+    // 1. to 6. Filter, split in blocks and code the blocks. The file must
+    //    not change while it's read or, when it's mapped, coded.
+    let mut buffer_out: Vec<u8> = huffman_codes::compress_buffer(& buffer_in, & cfg.options);
+    check_unchanged(& cfg.filename, & stamp_before, buffer_in.len())?;
+
+    // Encrypt all of the .johnny file, after the coding.
+    if let Some(secret) = & cfg.secret {
+        println!("...encrypting the .johnny file with ChaCha20-Poly1305");
+        buffer_out = encryption::encrypt(& buffer_out, secret)?;
+    }
 
-    }    
+    // The parity of the recovery record, of the .johnny file or of the
+    // encrypted file.
+    if cfg.recovery_percent > 0 {
+        println!("...adding a recovery record with {}% of parity", cfg.recovery_percent);
+        buffer_out = recovery::add_recovery_record(& buffer_out, cfg.recovery_percent);
+    }
 
     // 7. Write the final compressed byte buffer to file .johnny,
     let compressed_filename: String = cfg.filename.clone() + ".johnny"; 
-    write_byte_vec_to_file(& compressed_filename, &buffer_out);
+    write_byte_vec_to_file(& compressed_filename, &buffer_out)?;
     
     println!("...finish writing compressed file {}", compressed_filename);
+    Ok(())
 }
 
-fn decompress(cfg: & Config) {
+fn decompress(cfg: & Config) -> Result<()> {
     println!("...start decompressing file {}", cfg.filename);
 
     // 1. Read the file from disk into a byte buffer in binary representation.
-    let buffer_in: Vec<u8> = get_file_as_byte_vec( &cfg.filename );
-    let mut buffer_out: Vec<u8> = Vec::new();
+    let buffer_in: Input = get_file_as_input( &cfg.filename, cfg.no_mmap )?;
 
-    if buffer_in.len() <= 2 {
-        buffer_out = buffer_in;
+    // The decoder never reads after the last block, the encryption
+    // authenticates all of it's envelope so the signature block and the
+    // recovery record go first.
+    let buffer_in: &[u8] = if encryption::is_encrypted(& buffer_in) {
+        recovery::without_recovery_record(signature::unsigned_part(& buffer_in))
     } else {
+        & buffer_in
+    };
+
+    // An encrypted file is authenticated and decrypted before anything is
+    // decoded, with the secret it's never decoded unauthenticated.
+    let buffer_decrypted: Vec<u8>;
+    let buffer_in: &[u8] = match & cfg.secret {
+        Some(secret) => {
+            println!("...authenticating and decrypting the .johnny file");
+            buffer_decrypted = encryption::decrypt_with_limits(buffer_in, secret, & cfg.limits)?;
+            & buffer_decrypted
+        }
+        None => buffer_in,
+    };
 
-        // 2. Extract the symbols coding table to an internal representation. That is
-        //    the one with the Huffman coding inverted for decoding. 
-        let mut map_table = MappingTable::new();
-        let header_2_start = map_table.read_mapping_table_from_byte_buffer(& buffer_in);
-
-        // 3. Read the 16 bit header with the index (of the byte) of the start of
-        //    the data in the .johnny file. Read the second header with the number
-        //    of original symbols, or we could say original bytes. 
-        //    Apply the decoding table to the coded message bytes, buffer_in, and decode or
-        //    decompress it into a binary buffer_out. 
-        map_table.decode_the_data(& buffer_in, & mut buffer_out, header_2_start);
-
-    }
+    // 1. to 4. Read the headers, check the size of the message with the
+    //    limits, decode the blocks and undo the pre-filter.
+    let buffer_out: Vec<u8> = huffman_codes::decompress_buffer_with_limits(buffer_in, cfg.options.threads, cfg.options.dictionary.as_ref(), & cfg.limits)?;
 
     // 4. Write to the output file of the decoded binary or text data.
     let string_tmp = cfg.filename.clone();
     let (decompressed_filename, _): (&str, &str) = string_tmp.split_at(string_tmp.len() - ".johnny".len()); 
-    write_byte_vec_to_file(& decompressed_filename.to_string(), &buffer_out);
+    write_byte_vec_to_file(decompressed_filename, &buffer_out)?;
 
     println!("...finish writing decompressed file {}", decompressed_filename);
+    Ok(())
 }
 
-// Read binary file as byte vector (u8).
-// From: https://www.reddit.com/r/rust/comments/dekpl5/how_to_read_binary_data_from_a_file_into_a_vecu8/
-fn get_file_as_byte_vec(filename: &String) -> Vec<u8> {
-    let f = File::open(&filename).expect("file not found.");
-    let metadata = std::fs::metadata(&filename).expect("unable to read metadata.");
-    let mut buffer = vec![0; metadata.len() as usize];
-    let mut buf_reader = BufReader::new(f);
-    buf_reader.read(&mut buffer).expect("...buffer overflow.");
-    // Note: The file closes automatically when it gets out of scope.
+/// Trains the Huffman table of the sample corpus and writes it to the
+/// dictionary file, by default the sample filename plus .jtbl .
+fn train(cfg: & Config) -> Result<()> {
+    println!("...start training the dictionary with the sample {}", cfg.filename);
 
-    buffer
-}
+    let stamp_before = input::file_stamp(& cfg.filename);
+    let sample: Input = get_file_as_input( &cfg.filename, cfg.no_mmap )?;
+    let dictionary = Dictionary::train(& sample);
+    check_unchanged(& cfg.filename, & stamp_before, sample.len())?;
 
-// Write binary byte vector (u8) to a file.
-fn write_byte_vec_to_file(filename: &String, buffer: & Vec<u8>) {
-    let mut f = File::create(&filename).expect("no file found");
-    let mut buf_writer = BufWriter::new(& mut f);
-    buf_writer.write_all(&buffer).expect("...error while writing file!");
-    buf_writer.flush().expect("error while writing file!");
-}
-enum Elem {
-    Node(NodeType),
-    Leaf(LeafType),
-} 
-
-struct NodeType {
-    total_count: usize,
-    left:        Box<Elem>,
-    right:       Box<Elem>,
-}
+    let table_filename: String = match & cfg.table_filename {
+        Some(table_filename) => table_filename.clone(),
+        None                 => cfg.filename.clone() + ".jtbl",
+    };
+    write_byte_vec_to_file(& table_filename, & dictionary.to_bytes())?;
 
-struct LeafType {
-    pos: u8,
-    count: usize,
+    println!("...finish writing dictionary {:08x} with {} symbols to {}", dictionary.id, dictionary.num_symbols(), table_filename);
+    Ok(())
 }
 
-struct MappingTable {
-    vec_elem_count:  Vec<Elem>,        // Vec<(u8, usize)>,
-    print_text_char: bool,
-    map_encoding:    HashMap< u8, String >,
-    map_decoding:    HashMap< String, u8 >,
+/// Writes a new Ed25519 signing key to the key file, and it's verifying key
+/// to the key file plus .pub .
+fn keygen(cfg: & Config) -> Result<()> {
+    let (signing_key, verifying_key) = signature::generate_keys();
+    let public_filename: String = cfg.filename.clone() + ".pub";
+    // The signing key is private from the start, and an existing key file
+    // is never opened.
+    output::write_new_private_file(& cfg.filename, encryption::key_to_hex(& signing_key).as_bytes())
+        .map_err(|source| match source.kind() {
+            io::ErrorKind::AlreadyExists => Error::Usage(format!("The key file '{}' already exists", cfg.filename)),
+            _ => Error::Io { filename: cfg.filename.clone(), source },
+        })?;
+    write_byte_vec_to_file(& public_filename, encryption::key_to_hex(& verifying_key).as_bytes())?;
+
+    println!("...finish writing signing key {} and verifying key {}", cfg.filename, public_filename);
+    Ok(())
 }
 
-impl MappingTable {
-
-    fn new() -> MappingTable {
-        MappingTable {
-            vec_elem_count:  Vec::new(),
-            print_text_char: true,
-            map_encoding:    HashMap::new(),
-            map_decoding:    HashMap::new(),
-        }
-    }
-
-
-    ///******************
-    ///* Compress methods
-    ///******************
-
-    /// 2. Determine the frequency of the symbols (different bytes) in the input buffer.
-    fn get_buffer_byte_symbols_freq(& mut self, buffer_in: & Vec<u8>) {
-        let mut map_freq: [usize; 256] = [0; 256];
-        for &elem in buffer_in {
-            map_freq[elem as usize] += 1;     
-        }
-        // let total_bytes = buffer_in.len();
-
-        for (pos, e) in map_freq.iter().enumerate() {
-            if *e != 0 {
-                self.vec_elem_count.push(Elem::Leaf(
-                    LeafType {
-                        pos: pos as u8,
-                        count: *e,
-                    }
-                    ) );
-            }
-        }
-
-        // self.vec_node_count = map_freq.iter().enumerate()
-        //     .filter( |(_pos, e)| **e != 0 )
-        //     .map( |(pos, e)| Elem::Leaf( Leaf {pos: pos as u8, count: *e, } ))
-        //     .collect();
-    }
-
-    /// 3. By using a priority queue and the Huffman coding tree find the best    
-    ///    coding for each symbol of the message. Create a table for the code.
-    ///    This table inverted will also have to be known in the decoding phase.
-    fn generate_huffman_code(& mut self) {
-        
-        if self.vec_elem_count.len() == 1 {
-            if let Elem::Leaf( LeafType {pos, count: _} ) = self.vec_elem_count[0] {
-                self.map_encoding.insert( pos, "".to_string() );
-                return ();
-            }
-        }
-
-        while self.vec_elem_count.len() >= 2 {
-            self.vec_elem_count.sort_by( 
-                | elem_a : &Elem, elem_b : &Elem| 
-                {
-                    match elem_b {
-                        Elem::Node( NodeType { total_count, left: _, right: _} ) => {
-                            let total_count_b = total_count;
-                            match elem_a {
-                                Elem::Node( NodeType { total_count, left: _, right: _} ) => {
-                                            let total_count_a = &total_count;
-                                            total_count_b.partial_cmp(total_count_a).unwrap()
-                                        }
-                                Elem::Leaf( LeafType { pos: _, count } ) => {
-                                            let count_a = &count;                
-                                            total_count_b.partial_cmp(count_a).unwrap()
-                                        }    
-                                }
-                            }
-                        Elem::Leaf( LeafType { pos: _, count } ) => {
-                            let count_b = &count;
-                            match elem_a {
-                                Elem::Node( NodeType { total_count, left: _, right: _ } ) => {
-                                            let total_count_a = &total_count;
-                                            count_b.partial_cmp(total_count_a).unwrap()
-                                        }
-                                Elem::Leaf( LeafType { pos: _, count } ) => {
-                                            let count_a = &count;
-                                            count_b.partial_cmp(count_a).unwrap()
-                                        }    
-                            }
-                        } 
-                    }
-                } );
-            // Remove the elements from the end.
-            // Search for lowest element.
-            let elem_0 = self.vec_elem_count.remove(self.vec_elem_count.len() - 1);
-            // Search for the second lowest element.
-            let elem_1 = self.vec_elem_count.remove(self.vec_elem_count.len() - 1);
-
-            
-            let node = match elem_0 {
-                Elem::Node( NodeType { total_count, left: _, right: _} ) => {
-                    let total_count_0 = total_count;
-                    match elem_1 {
-                        Elem::Node( NodeType { total_count, left: _, right: _} ) => {
-                                    let total_count_1 = &total_count;
-                                    Elem::Node( NodeType{
-                                        total_count: total_count_0 + total_count_1,
-                                        left:        Box::new(elem_0),
-                                        right:       Box::new(elem_1),
-                                    })
-                                }
-                        Elem::Leaf( LeafType { pos: _, count } ) => {
-                                    let count_1 = &count;                
-                                    Elem::Node( NodeType{
-                                        total_count: total_count_0 + count_1,
-                                        left:        Box::new(elem_0),
-                                        right:       Box::new(elem_1),
-                                    })
-                                }    
-                        }
-                    }
-                Elem::Leaf( LeafType { pos: _, count } ) => {
-                    let count_0 = &count;
-                    match elem_1 {
-                        Elem::Node( NodeType { total_count, left: _, right: _ } ) => {
-                                    let total_count_1 = &total_count;
-                                    Elem::Node( NodeType{
-                                        total_count: count_0 + total_count_1,
-                                        left:        Box::new(elem_0),
-                                        right:       Box::new(elem_1),
-                                    })
-                                }
-                        Elem::Leaf( LeafType { pos: _, count } ) => {
-                                    let count_1 = &count;
-                                    Elem::Node( NodeType{
-                                        total_count: count_0 + count_1,
-                                        left:        Box::new(elem_0),
-                                        right:       Box::new(elem_1),
-                                    })
-                                }    
-                    }
-                } 
-            };
+/// Appends the signature block of the signing key to the file, the file is
+/// replaced by the signed file.
+fn sign(cfg: & Config) -> Result<()> {
+    println!("...start signing file {}", cfg.filename);
+    let signing_key = match & cfg.secret {
+        Some(Secret::Key(key)) => key,
+        _ => unreachable!("the key file is checked with the options"),
+    };
+    let buffer_in = get_file_as_byte_vec(& cfg.filename)?;
+    let buffer_out = signature::sign(& buffer_in, signing_key)?;
+    write_byte_vec_to_file(& cfg.filename, & buffer_out)?;
+
+    let verifying_key = signature::verifying_key(signing_key);
+    println!("...finish signing file {} with the verifying key {}", cfg.filename, encryption::key_to_hex(& verifying_key).trim_end());
+    Ok(())
+}
 
-            self.vec_elem_count.push(node);
+/// Checks the signature block of the file with the verifying key.
+fn verify(cfg: & Config) -> Result<()> {
+    println!("...start verifying file {}", cfg.filename);
+    let verifying_key = match & cfg.secret {
+        Some(Secret::Key(key)) => key,
+        _ => unreachable!("the key file is checked with the options"),
+    };
+    let buffer_in: Input = get_file_as_input( &cfg.filename, cfg.no_mmap )?;
+    let signed = signature::verify(& buffer_in, verifying_key)?;
+
+    println!("...the signature of the {} bytes of {} is valid", signed.len(), cfg.filename);
+    Ok(())
+}
 
+/// Repairs the damaged shards of the file with it's recovery record, and
+/// replaces the file. If too many shards are damaged, the blocks that are
+/// intact are decoded to the decompressed filename plus .partial .
+fn repair(cfg: & Config) -> Result<()> {
+    println!("...start repairing file {}", cfg.filename);
+    let buffer_in = get_file_as_byte_vec(& cfg.filename)?;
+
+    // The signature block isn't in the record, it goes back after the repair.
+    let unsigned = signature::unsigned_part(& buffer_in);
+    let signature_block = & buffer_in[unsigned.len()..];
+    match recovery::repair(unsigned) {
+        Ok((_, 0)) => println!("...no damage found in file {}", cfg.filename),
+        Ok((mut buffer_out, damaged)) => {
+            buffer_out.extend_from_slice(signature_block);
+            write_byte_vec_to_file(& cfg.filename, & buffer_out)?;
+            println!("...finish repairing {} damaged shards of file {}", damaged, cfg.filename);
         }
-
-        println!("...finished generating huffman code tree!");
-        self.print_huffman_tree();
-        self.get_huffman_code_from_tree();        
-    }
-
-    fn get_huffman_code_from_tree(& mut self) {
-        println!("...get_huffman_code_from_tree:");
-        let curr_node: & Elem = & self.vec_elem_count[0];
-        let start_code = "".to_string();
-        let mut map: HashMap< u8, String > = HashMap::new();
-        self.transverse_tree_get_huffman_codes(curr_node, start_code, & mut map );
-        self.map_encoding = map;
-    }
-
-    /// Transverse the tree recursively.
-    fn transverse_tree_get_huffman_codes(& self, curr_elem: & Elem, code: String, map_encoding_p: & mut HashMap< u8, String > ) {
-        match curr_elem {
-            Elem::Node( NodeType { total_count: _, left, right} ) => {
-                let new_code_left = code.clone() + "0";
-                self.transverse_tree_get_huffman_codes(left,new_code_left, map_encoding_p);
-                let new_code_right = code.clone() + "1";
-                self.transverse_tree_get_huffman_codes(right,new_code_right, map_encoding_p);
-            }
-            Elem::Leaf( LeafType { pos, count: _ } ) => {
-                let symbol_byte:u8 = *pos;
-                let huffman_code = code; 
-                map_encoding_p.insert(symbol_byte, huffman_code);
-            }
+        Err(error @ Error::Unrepairable { .. }) => {
+            let (buffer_out, damaged_blocks) = recovery::recover_blocks(unsigned, cfg.options.dictionary.as_ref(), & cfg.limits)?;
+            let partial_filename = format!("{}.partial", & cfg.filename[..cfg.filename.len() - ".johnny".len()]);
+            write_byte_vec_to_file(& partial_filename, & buffer_out)?;
+            println!("...finish writing the partial file {}, {} damaged blocks are zeros", partial_filename, damaged_blocks);
+            return Err(error);
         }
+        Err(error) => return Err(error),
     }
+    Ok(())
+}
 
-    /// Prints the Huffman coding tree.
-    fn print_huffman_tree(& self) {
-        println!("...huffman code tree:");
-
-        let curr_node: & Elem = &self.vec_elem_count[0];
-        let offset = 0;
-        self.print_tree_node(curr_node, offset, false, false, "".to_string() );
-    }    
-
-    /// Transverse the tree recursively for printing the nodes and leafs of the tree.
-    fn print_tree_node(& self, curr_elem: & Elem, offset: usize, changed_line: bool, other_leaf: bool, code: String) -> bool {
-        match curr_elem {
-            Elem::Node( NodeType { total_count, left, right} ) => {
-                if changed_line {
-                    let offset_string = " ".to_string().repeat(offset);
-                    print!{"{}", offset_string};
-                }
-                print!(" |-  {:3} -| ", total_count);
-                let new_code_left = code.clone() + "0";
-                let ret_flag = self.print_tree_node(left, offset + 12, false, false, new_code_left);
-                let new_code_right = code.clone() + "1";
-                self.print_tree_node(right,offset + 12, true, ret_flag, new_code_right);
-                return false;
-            }
-            Elem::Leaf( LeafType { pos, count } ) => {
-                if other_leaf {
-                    let offset_string = " ".to_string().repeat(offset);
-                    print!{"{}", offset_string};
-                }
-                
-                if self.print_text_char {
-                    let mut c = *pos as char;
-                    if c == '\n' {
-                        c = '\\';
-                    }
-                    println!(" <-{} {:3}  {} ->", c, count, code);
-                } else {
-                    println!(" <-{} {:3}  {} ->", pos, count, code);
-                }
-                return true;
+/// Compresses and decompresses the generated corpora with the options, and
+/// prints the speed, the ratio and the bytes spent on the tables.
+fn bench(cfg: & Config) -> Result<()> {
+    println!("...benchmark of {} bytes of each corpus, the best of {} runs", cfg.bench_size, BENCH_RUNS);
+    huffman_codes::set_quiet(true);
+    println!("{:>12} {:>10} {:>10} {:>7} {:>12} {:>12} {:>10} {:>8}",
+             "corpus", "bytes", "johnny", "ratio", "comp MB/s", "decomp MB/s", "tables", "blocks");
+    for kind in Corpus::ALL.iter() {
+        let buffer_in = corpus::generate(*kind, cfg.bench_size);
+
+        let mut best_compress = f64::MAX;
+        let mut best_decompress = f64::MAX;
+        let mut result = (Vec::new(), CompressStats::default());
+        for _ in 0..BENCH_RUNS {
+            let start = Instant::now();
+            result = huffman_codes::compress_buffer_with_stats(& buffer_in, & cfg.options);
+            best_compress = best_compress.min(start.elapsed().as_secs_f64());
+
+            // A decoder that fails is reported, and the other corpora go on.
+            let start = Instant::now();
+            let round_trip = huffman_codes::decompress_buffer(& result.0, cfg.options.threads);
+            best_decompress = best_decompress.min(start.elapsed().as_secs_f64());
+            if round_trip.ok().as_ref() != Some(& buffer_in) {
+                best_decompress = f64::NAN;
+                break;
             }
         }
-    }
-
-    /// 4. Write the table to the beginning of byte buffer and the 16 bit heading,
-    ///    with the start of the data.
-    fn write_mapping_table_to_byte_buffer(& mut self, buffer_out: & mut Vec<u8>) {
-
-        // Fill in the map decoding, from String to u8 byte.
-        self.map_decoding = self.map_encoding.iter()
-            .map(|(byte_a_start, string_a_end)| {
-                let string_b_start = string_a_end.clone();
-                let byte_b_end: u8 = byte_a_start.clone();  
-
-                (string_b_start, byte_b_end)
-            }).collect();    
-
-        // Fill in the header with zeros.
-        buffer_out.push(0);
-        buffer_out.push(0);
-
-        let mut vec_tmp: Vec<(String, u8)> = self.map_decoding.iter()
-                    .map(|(k, v)| (k.clone(), *v) )
-                    .collect();
-
-        vec_tmp.sort_by(|(key_a, _val_a), (key_b, _val_b)| key_a.cmp(key_b));
-
-        for (key, value) in & vec_tmp {
-            buffer_out.extend_from_slice(key.as_bytes());
-            buffer_out.push('\n' as u8);
-            buffer_out.push(*value);
+        if best_decompress.is_nan() {
+            println!("{:>12} ...doesn't round trip with these options.", kind.to_string());
+            continue;
         }
 
-        println!("\n map_decoding: \n{:?}\n\n", vec_tmp);
-
-        // Fill in the header with the position of one plus the end of
-        // the header or the position of the start of the compressed data.
-        let len = buffer_out.len();
-        let len_first: u8 = (len & 0x0000_00FF) as u8;
-        let len_second: u8 = ((len & 0x0000_FF00) >> 8) as u8;
-        buffer_out[0] = len_second;
-        buffer_out[1] = len_first;
+        let (buffer_johnny, stats) = result;
+        let mega_bytes = buffer_in.len() as f64 / 1_000_000.0;
+        println!("{:>12} {:>10} {:>10} {:>7.3} {:>12.1} {:>12.1} {:>10} {:>8}",
+                 kind.to_string(), buffer_in.len(), buffer_johnny.len(),
+                 buffer_johnny.len() as f64 / buffer_in.len() as f64,
+                 mega_bytes / best_compress, mega_bytes / best_decompress,
+                 stats.table_bytes, stats.num_blocks);
     }
-    
-    /// 5. With the new dictionary, encode the message in bytes to a byte buffer.
-    /// 6. Write the first 8 byte with an usize 64 bit's representing the number
-    ///    of bytes or total symbols in the original file of the message.    
-    fn encode_the_data(&self, buffer_in: & Vec<u8>, buffer_out: & mut Vec<u8>) {
-
-        let start_2_header = buffer_out.len();
-
-        println!("\n...start index of data {} ", start_2_header);
-
-        // Fill in the second header, for the 64 bit, 8 bytes number of symbols,
-        // with zeros.
-        buffer_out.push(0);
-        buffer_out.push(0);
-        buffer_out.push(0);
-        buffer_out.push(0);
-
-        buffer_out.push(0);
-        buffer_out.push(0);
-        buffer_out.push(0);
-        buffer_out.push(0);
-
-        // Encode from buffer_in into buffer_out_after the the decoding table.
-        let mut symbol_counter: usize = 0;  
-        let mut index_out_bit: u8     = 0;
-        let mut byte_out:u8           = 0b0000_0000;
-
-        for byte in buffer_in {
-            symbol_counter += 1;
-            // Get the byte.
-            // Get the encoding string.
-            let string_enc= self.map_encoding.get(byte).unwrap();
-        
-            // Convert the encoding string into the next bit's in the buffer_out.
-            // At the end of each bytes writes to the buffer_out
-            // for c in string_enc.chars(){    // UTF-8 way
-            for c in string_enc.bytes(){        // Binary way.
-                // print!("{}", c);
-                // if c == '1'  {              // UTF-8 way
-                if c == b'1'  {                     // Binary way.
-                    byte_out |= 0b1000_0000 >> index_out_bit;
-                }
-                index_out_bit += 1;
-                if index_out_bit >= 8 {
-                    index_out_bit = 0;
-                    buffer_out.push(byte_out);
-                    byte_out = 0b0000_0000;
-                }
-            }
-        }
-        if index_out_bit > 0 {
-            buffer_out.push(byte_out);
-        }
-
-        // Debug: 
-        // println!();
-        // buffer_out[start_2_header + 8 ..].iter().map(|byte| print!("{:b} ", byte)).count();
-
+    huffman_codes::set_quiet(false);
+    Ok(())
+}
 
-        // Fill in the second header with the number of symbols in the file data section,
-        // or by other words the number of bytes of the original file.
-        // We need this second header for the deconding fase, because our compressed 
-        // symbols are variable length in size, from zero to n bits.
-        
-        /*
-        let symbol_ct_0: u8 = ((symbol_counter & (0x00FF_usize <<  0)) >>  0) as u8;
-        let symbol_ct_1: u8 = ((symbol_counter & (0x00FF_usize <<  8)) >>  8) as u8;
-        let symbol_ct_2: u8 = ((symbol_counter & (0x00FF_usize << 16)) >> 16) as u8;
-        let symbol_ct_3: u8 = ((symbol_counter & (0x00FF_usize << 24)) >> 24) as u8;
-        let symbol_ct_4: u8 = ((symbol_counter & (0x00FF_usize << 32)) >> 32) as u8;
-        let symbol_ct_5: u8 = ((symbol_counter & (0x00FF_usize << 40)) >> 40) as u8;
-        let symbol_ct_6: u8 = ((symbol_counter & (0x00FF_usize << 48)) >> 48) as u8;
-        let symbol_ct_7: u8 = ((symbol_counter & (0x00FF_usize << 56)) >> 56) as u8;
-                
-        buffer_out[start_2_header + 0] = symbol_ct_7;
-        buffer_out[start_2_header + 1] = symbol_ct_6;
-        buffer_out[start_2_header + 2] = symbol_ct_5;
-        buffer_out[start_2_header + 3] = symbol_ct_4;
-        buffer_out[start_2_header + 4] = symbol_ct_3;
-        buffer_out[start_2_header + 5] = symbol_ct_2;
-        buffer_out[start_2_header + 6] = symbol_ct_1;
-        buffer_out[start_2_header + 7] = symbol_ct_0;
-        */
-
-        // Compressed version.
-        for i in 0..8 {
-            let symbol_ct: u8 = ((symbol_counter & (0x00FF_usize <<  (i*8))) >>  (i*8)) as u8;
-            buffer_out[start_2_header + 7 - i] = symbol_ct;
+// Memory maps the input file, or reads it as a byte vector if it can't be
+// mapped or the mapping is turned off with --no-mmap.
+fn get_file_as_input(filename: &str, no_mmap: bool) -> Result<Input> {
+    let mapped = if no_mmap { None } else { input::map_file(filename) };
+    match mapped {
+        Some(input) => {
+            println!("...memory mapping the input file");
+            Ok(input)
         }
-
-        println!("\n...symbol_counter or original file byte size {} ", symbol_counter);
-
+        None => Ok(Input::Owned(get_file_as_byte_vec(filename)?)),
     }
+}
 
-    ///********************
-    ///* Decompress methods
-    ///********************
-
-    /// 2. Extract the symbols coding table to an internal representation. That is
-    ///    the one with the Huffman coding inverted for decoding. 
-    fn read_mapping_table_from_byte_buffer(&mut self, buffer_in: & Vec<u8>) -> usize {
-
-        // Read the first header with the position of one plus the end of
-        // the header or the position of the start of the compressed data.
-        let len_second = buffer_in[0];
-        let len_first  = buffer_in[1];
-        let header_2_start: usize = (len_second as usize) << 8 | (len_first as usize);  
-
-        println!("\n...header_1_start index in the .johnny compressed input  {} ", header_2_start);
-
-
-        println!("\n...decoding table:\n");
-
-        let mut string_key_acc = String::new();
-        let mut flag_dec_value = false;
-        for i in 2..header_2_start {
-            let c = buffer_in[i] as char;
-            if !flag_dec_value {
-                if c == '\n' {
-                    flag_dec_value = true;
-
-                } else {
-                    string_key_acc.push(c);
-                }
-            } else {
-                flag_dec_value = false;
-                let value_byte = buffer_in[i];
-
-                self.map_decoding.insert(string_key_acc.clone(), value_byte);
-                if self.print_text_char {
-                    // println!("{} -> {}", string_key_acc, value_byte as char);
-                } else {
-                    println!("{} -> {}", string_key_acc, value_byte);
-                }
-                string_key_acc.clear();
+/// The I/O error of the file.
+fn io_error(filename: &str) -> impl FnOnce(io::Error) -> Error + '_ {
+    move |source| Error::Io { filename: filename.to_string(), source }
+}
 
-            }
-        }
-        
-        header_2_start
-    }
+// Read binary file as byte vector (u8), until the end of the file. A single
+// read can return less bytes than asked, and the length of the metadata is
+// zero for pipes and /proc files, so it's only the starting capacity.
+fn get_file_as_byte_vec(filename: &str) -> Result<Vec<u8>> {
+    let mut f = File::open(filename).map_err(io_error(filename))?;
+    let size_hint = f.metadata().map(|metadata| metadata.len() as usize).unwrap_or(0);
+    let mut buffer: Vec<u8> = Vec::with_capacity(size_hint);
+    f.read_to_end(& mut buffer).map_err(io_error(filename))?;
+    // Note: The file closes automatically when it gets out of scope.
 
-    // 3. Read the 16 bit header with the index (of the byte) of the start of
-    //    the data in the .johnny file. Read the second header with the number
-    //    of original symbols, or we could say original bytes. 
-    //    Apply the decoding table to the coded message bytes, buffer_in, and decode or
-    //    decompress it into a binary buffer_out. 
-    fn decode_the_data(&mut self, buffer_in: & Vec<u8>, buffer_out: & mut Vec<u8>, header_2_start: usize) {
-
-        // Read the second header with the number of symbols or bytes of the original
-        // file. This is important because the number of bit's for each compressed symbol
-        // varies with the symbol and implements exactly a variable minimal Huffman encoding.   
-
-        // Compressed version.
-        let mut symbol_counter: usize = 0;
-        for i in 0..8 {
-            let symbol_ct = buffer_in[header_2_start + 7 - i];
-            symbol_counter |= (symbol_ct as usize) << (i*8);
-        }
+    Ok(buffer)
+}
 
-        println!("\n...symbol_counter or original file byte size {} ", symbol_counter);
-
-        // We obtain the data sub_range slice to iterate over it.
-        let sub_range_buffer_in = &buffer_in[header_2_start + 8 ..];
-
-        let mut string_key = String::new();
-        'outer: for byte in sub_range_buffer_in {
-            for index_in_bit in 0_u8..8_u8 {
-                let byte_out = (*byte & (0b1000_0000 >> index_in_bit)) >> (7 - index_in_bit);
-                if byte_out == 1 {
-                    string_key.push('1');
-                    // print!("1");
-                } else {
-                    string_key.push('0');
-                    // print!("0");
-                }
-                if let Some(value_byte) = self.map_decoding.get(& string_key) {
-                    string_key.clear();
-                    buffer_out.push(*value_byte);
-                    // print!("({})", *value_byte as char);
-                    
-                    // To manage the not full filled last byte.
-                    symbol_counter -= 1;
-                    if symbol_counter <= 0 {
-                        break 'outer;    
-                    }
-                }
-            }
+/// A regular file must have the same stamp after it's used as before it was
+/// read, and the length of the bytes that were read.
+fn check_unchanged(filename: &str, stamp_before: & Option<FileStamp>, len_read: usize) -> Result<()> {
+    if let Some(stamp) = stamp_before {
+        if stamp.len != len_read as u64 || input::file_stamp(filename).as_ref() != Some(stamp) {
+            return Err(Error::FileChanged(filename.to_string()));
         }
-    
-        println!();
     }
+    Ok(())
+}
 
+// Write binary byte vector (u8) to a file, through a temporary file that is
+// renamed into place, so the file is never left half written.
+fn write_byte_vec_to_file(filename: &str, buffer: &[u8]) -> Result<()> {
+    output::write_file_atomically(filename, buffer).map_err(io_error(filename))
 }
@@ -0,0 +1,151 @@
+// The output file, written to a temporary file and renamed into place.
+//
+// A crash or a Ctrl-C in the middle of File::create and write would leave a
+// truncated .johnny or decompressed file with the final name, that looks
+// valid. The bytes go first to a temporary file in the same directory, that
+// is synced to the disk and then renamed over the final name, the rename
+// is atomic so the final file is either the old one or the complete new one.
+// The temporary file is removed if the write fails or on Ctrl-C.
+//
+// The temporary file is always a new file, a stale one of an old process
+// with the same id, or a file or a symlink that someone else put there, is
+// never opened, the next name is tried. When the final file is replaced the
+// new one gets it's permissions, a private file stays private. A secret key
+// is written without a temporary file, to a new file that is private from
+// the start.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
+
+/// The temporary files being written, removed on Ctrl-C.
+static TEMP_FILES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Exit code of an interrupted program, 128 plus SIGINT.
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Number of names tried for the temporary file.
+const MAX_TEMP_NAMES: u32 = 100;
+
+/// On Ctrl-C, or SIGTERM, removes the temporary files and exits.
+pub fn remove_temp_files_on_interrupt() {
+    let result = ctrlc::set_handler(|| {
+        if let Ok(temp_files) = TEMP_FILES.lock() {
+            for temp_path in temp_files.iter() {
+                let _ = fs::remove_file(temp_path);
+            }
+        }
+        eprintln!(" Error: interrupted, the output file wasn't written");
+        process::exit(EXIT_INTERRUPTED);
+    });
+    if result.is_err() {
+        eprintln!("...can't set the Ctrl-C handler, an interrupted write may leave a temporary file.");
+    }
+}
+
+/// Writes the buffer to the file, or leaves the file as it was if the write
+/// fails.
+pub fn write_file_atomically(filename: &str, buffer: &[u8]) -> io::Result<()> {
+    let (temp_path, mut temp_file) = create_temp_file(Path::new(filename))?;
+    let result = copy_permissions(Path::new(filename), & temp_file)
+        .and_then(|_| write_and_sync(& mut temp_file, buffer))
+        .and_then(|_| fs::rename(& temp_path, filename));
+    if result.is_err() {
+        let _ = fs::remove_file(& temp_path);
+    }
+    unregister(& temp_path);
+    result?;
+
+    // The rename is only durable after the directory is synced, not all
+    // the systems can open a directory so it's best effort.
+    if let Some(dir) = Path::new(filename).parent() {
+        let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+        if let Ok(dir_file) = File::open(dir) {
+            let _ = dir_file.sync_all();
+        }
+    }
+    Ok(())
+}
+
+/// Writes the buffer to a new file that only the user can read and write,
+/// for a secret key. An existing file is never opened, the error is then
+/// AlreadyExists, and there's no temporary file with the secret in it's
+/// bytes and wider permissions. The file is removed if the write fails or
+/// on Ctrl-C.
+pub fn write_new_private_file(filename: &str, buffer: &[u8]) -> io::Result<()> {
+    let mut open_options = OpenOptions::new();
+    open_options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        open_options.mode(0o600);
+    }
+    let mut file = open_options.open(filename)?;
+    let path = Path::new(filename);
+    register(path);
+    let result = write_and_sync(& mut file, buffer);
+    if result.is_err() {
+        let _ = fs::remove_file(path);
+    }
+    unregister(path);
+    result
+}
+
+/// Creates a new temporary file for the final file, and registers it to be
+/// removed on Ctrl-C. A name that is taken is left alone.
+fn create_temp_file(path: & Path) -> io::Result<(PathBuf, File)> {
+    for attempt in 0..MAX_TEMP_NAMES {
+        let temp_path = temp_path_for(path, attempt);
+        match OpenOptions::new().write(true).create_new(true).open(& temp_path) {
+            Ok(file) => {
+                register(& temp_path);
+                return Ok((temp_path, file));
+            }
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error),
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::AlreadyExists, "all the names of the temporary file are taken"))
+}
+
+/// The permissions of the final file, if it exists, for the new one.
+fn copy_permissions(path: & Path, temp_file: & File) -> io::Result<()> {
+    match fs::metadata(path) {
+        Ok(metadata) => temp_file.set_permissions(metadata.permissions()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+fn write_and_sync(file: & mut File, buffer: &[u8]) -> io::Result<()> {
+    let mut buf_writer = BufWriter::new(& mut *file);
+    buf_writer.write_all(buffer)?;
+    buf_writer.flush()?;
+    drop(buf_writer);
+    file.sync_all()
+}
+
+/// The temporary file, hidden and in the same directory as the final file so
+/// the rename doesn't cross file systems, ex: "dir/.name.johnny.1234.tmp",
+/// and "dir/.name.johnny.1234.1.tmp" for the next attempt.
+fn temp_path_for(path: & Path, attempt: u32) -> PathBuf {
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    match attempt {
+        0 => path.with_file_name(format!(".{}.{}.tmp", name, process::id())),
+        _ => path.with_file_name(format!(".{}.{}.{}.tmp", name, process::id(), attempt)),
+    }
+}
+
+fn register(temp_path: & Path) {
+    if let Ok(mut temp_files) = TEMP_FILES.lock() {
+        temp_files.push(temp_path.to_path_buf());
+    }
+}
+
+fn unregister(temp_path: & Path) {
+    if let Ok(mut temp_files) = TEMP_FILES.lock() {
+        temp_files.retain(|path| path != temp_path);
+    }
+}
@@ -0,0 +1,52 @@
+// Runs the coding of the blocks in several threads.
+//
+// The blocks are independent, each thread takes the next block that is left
+// and the results are put back in the order of the blocks, so the output is
+// the same for any number of threads.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// The number of threads of the command line, 0 is one thread for each core.
+pub fn num_threads(threads: usize) -> usize {
+    if threads > 0 {
+        return threads;
+    }
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+/// Applies the function to each item in up to num_threads threads, and
+/// returns the results in the order of the items.
+pub fn map_in_order<T, R, F>(items: &[T], num_threads: usize, function: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let num_threads = num_threads.min(items.len());
+    if num_threads <= 1 {
+        return items.iter().map(function).collect();
+    }
+
+    let next_item = AtomicUsize::new(0);
+    let mut vec_results: Vec<(usize, R)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..num_threads)
+            .map(|_| scope.spawn(|| {
+                let mut vec_done: Vec<(usize, R)> = Vec::new();
+                loop {
+                    let index = next_item.fetch_add(1, Ordering::Relaxed);
+                    if index >= items.len() {
+                        break;
+                    }
+                    vec_done.push((index, function(&items[index])));
+                }
+                vec_done
+            }))
+            .collect();
+        handles.into_iter()
+            .flat_map(|handle| handle.join().expect("...a coding thread failed."))
+            .collect()
+    });
+    vec_results.sort_by_key(|(index, _result)| *index);
+    vec_results.into_iter().map(|(_index, result)| result).collect()
+}
@@ -0,0 +1,297 @@
+// The recovery record of the .johnny files, for the long term archives.
+//
+// A flipped bit in the coded data of a block changes all the symbols after
+// it, and a flipped bit in a block header loses the position of all the
+// blocks after it. The recovery record is appended to the end of the file,
+// after the encryption if any, and it has:
+//
+//   * the Reed-Solomon parity of the file. The bytes before the record are
+//     split in at most 256 data shards of the same size, the last one padded
+//     with zeros, and the parity shards are a percentage of them. With the
+//     CRC32 of each shard the damaged shards are known, and as many damaged
+//     shards as parity shards are rebuilt, in the data or in the parity.
+//   * a copy of the .johnny header and of each block header, with the
+//     position and the CRC32 of the body of each block. These are the points
+//     where the decoding starts again after a damaged block, so when there
+//     are too many damaged shards, the blocks that are intact are still
+//     decoded and the damaged ones are replaced by zeros.
+//
+// The table of the record, after the parity shards:
+//
+//   byte  0      record version
+//   bytes 1..9   number of bytes before the record, big endian
+//   bytes 9..13  size of a shard, big endian
+//   bytes 13..15 number of data shards, big endian
+//   bytes 15..17 number of parity shards, big endian
+//                the CRC32 of each data and parity shard, big endian
+//   byte         1 if the blocks follow, 0 for an encrypted file
+//                the .johnny header, the number of blocks, and for each
+//                block the position of the body, the block header and the
+//                CRC32 of the body
+//                the CRC32 of the table, the number of bytes of the table
+//                and the magic "JREC", 12 bytes
+//
+// The table itself isn't repaired, it has it's own CRC32.
+
+use std::convert::TryFrom;
+
+use reed_solomon_erasure::galois_8::ReedSolomon;
+
+use crate::dictionary::Dictionary;
+use crate::error::{bytes_at, Error, Result};
+use crate::header::{BlockHeader, Header, HEADER_SIZE, BLOCK_HEADER_SIZE};
+use crate::limits::Limits;
+
+pub const MAGIC: &[u8; 4] = b"JREC";
+pub const RECOVERY_VERSION: u8 = 1;
+
+/// The CRC32, the length of the table and the magic.
+const FOOTER_SIZE: usize = 12;
+/// GF(2^8) has at most 256 shards, data and parity.
+const MAX_SHARDS: usize = 256;
+/// A small file gets shards of at least 64 bytes, not 256 tiny shards.
+const MIN_SHARD_SIZE: usize = 64;
+/// The position of the body, the block header and the CRC32.
+const BLOCK_ENTRY_SIZE: usize = 8 + BLOCK_HEADER_SIZE + 4;
+
+/// The position of the body of a block, it's block header and the CRC32 of
+/// the body.
+type BlockEntry = (usize, BlockHeader, u32);
+
+/// The record of a file, read from it's end.
+struct Record {
+    protected_len: usize,
+    shard_size:    usize,
+    data_shards:   usize,
+    parity_shards: usize,
+    shard_crcs:    Vec<u32>,
+    header:        Option<Header>,
+    blocks:        Vec<BlockEntry>,
+    /// The start of the parity shards, and of the table after them.
+    record_start:  usize,
+    table_start:   usize,
+}
+
+/// True if the buffer ends with the magic of a recovery record.
+pub fn has_recovery_record(buffer_in: &[u8]) -> bool {
+    buffer_in.len() >= FOOTER_SIZE && buffer_in.ends_with(MAGIC)
+}
+
+/// The bytes before the recovery record, or all of the buffer if it has
+/// no valid record.
+pub fn without_recovery_record(buffer_in: &[u8]) -> &[u8] {
+    match read_record(buffer_in) {
+        Ok(record) => & buffer_in[..record.protected_len],
+        Err(_)     => buffer_in,
+    }
+}
+
+/// The buffer with a recovery record of percent parity shards for each 100
+/// data shards, from 1 to 100.
+pub fn add_recovery_record(buffer_in: &[u8], percent: usize) -> Vec<u8> {
+    let percent = percent.clamp(1, 100);
+    // 1. The most data shards that, with their parity shards, fit in 256.
+    let mut max_data_shards = MAX_SHARDS - 1;
+    while max_data_shards + parity_shards_for(max_data_shards, percent) > MAX_SHARDS {
+        max_data_shards -= 1;
+    }
+    let shard_size = buffer_in.len().div_ceil(max_data_shards).max(MIN_SHARD_SIZE);
+    let data_shards = buffer_in.len().div_ceil(shard_size).max(1);
+    let parity_shards = parity_shards_for(data_shards, percent);
+
+    // 2. The parity of the data shards.
+    let mut shards = split_shards(buffer_in, shard_size, data_shards);
+    shards.extend(std::iter::repeat_n(vec![0u8; shard_size], parity_shards));
+    ReedSolomon::new(data_shards, parity_shards)
+        .and_then(|reed_solomon| reed_solomon.encode(& mut shards))
+        .expect("...the number of shards is in the limits of GF(2^8).");
+
+    // 3. The parity shards and the record.
+    let mut buffer_out: Vec<u8> = buffer_in.to_vec();
+    for shard in shards[data_shards..].iter() {
+        buffer_out.extend_from_slice(shard);
+    }
+    let table_start = buffer_out.len();
+    buffer_out.push(RECOVERY_VERSION);
+    buffer_out.extend_from_slice(& (buffer_in.len() as u64).to_be_bytes());
+    buffer_out.extend_from_slice(& (shard_size as u32).to_be_bytes());
+    buffer_out.extend_from_slice(& (data_shards as u16).to_be_bytes());
+    buffer_out.extend_from_slice(& (parity_shards as u16).to_be_bytes());
+    for shard in shards.iter() {
+        buffer_out.extend_from_slice(& crc32fast::hash(shard).to_be_bytes());
+    }
+    write_block_index(buffer_in, & mut buffer_out);
+    let table_crc = crc32fast::hash(& buffer_out[table_start..]);
+    let table_len = buffer_out.len() - table_start;
+    buffer_out.extend_from_slice(& table_crc.to_be_bytes());
+    buffer_out.extend_from_slice(& (table_len as u32).to_be_bytes());
+    buffer_out.extend_from_slice(MAGIC);
+    buffer_out
+}
+
+/// Repairs the damaged shards of the file with the parity of the recovery
+/// record. Returns the repaired file and the number of damaged shards, zero
+/// if the file was intact.
+pub fn repair(buffer_in: &[u8]) -> Result<(Vec<u8>, usize)> {
+    let record = read_record(buffer_in)?;
+
+    // 1. The shards that don't have their CRC32 are damaged.
+    let mut shards = split_shards(& buffer_in[..record.protected_len], record.shard_size, record.data_shards);
+    let parity_end = record.record_start + record.parity_shards * record.shard_size;
+    shards.extend(buffer_in[record.record_start..parity_end].chunks(record.shard_size).map(|shard| shard.to_vec()));
+    let mut shards: Vec<Option<Vec<u8>>> = shards.into_iter().zip(record.shard_crcs.iter())
+        .map(|(shard, crc)| if crc32fast::hash(& shard) == *crc { Some(shard) } else { None })
+        .collect();
+    let damaged = shards.iter().filter(|shard| shard.is_none()).count();
+    if damaged == 0 {
+        return Ok((buffer_in.to_vec(), 0));
+    }
+    if damaged > record.parity_shards {
+        return Err(Error::Unrepairable { damaged, parity: record.parity_shards });
+    }
+
+    // 2. Rebuild the damaged shards, and the file with the same record.
+    ReedSolomon::new(record.data_shards, record.parity_shards)
+        .and_then(|reed_solomon| reed_solomon.reconstruct(& mut shards))
+        .map_err(|_| Error::Unrepairable { damaged, parity: record.parity_shards })?;
+    let mut buffer_out: Vec<u8> = Vec::with_capacity(buffer_in.len());
+    for shard in shards.iter().flatten() {
+        buffer_out.extend_from_slice(shard);
+    }
+    let padding = record.data_shards * record.shard_size - record.protected_len;
+    buffer_out.drain(record.protected_len..record.protected_len + padding);
+    buffer_out.extend_from_slice(& buffer_in[record.table_start..]);
+    Ok((buffer_out, damaged))
+}
+
+/// Decodes the blocks of the file that are intact, with the block headers
+/// of the recovery record, and fills the damaged blocks with zeros. Returns
+/// the message and the number of damaged blocks. The sizes of the block
+/// headers of the record are checked with the limits before the message is
+/// allocated, as in the decompression.
+pub fn recover_blocks(buffer_in: &[u8], dictionary: Option<& Dictionary>, limits: & Limits) -> Result<(Vec<u8>, usize)> {
+    let record = read_record(buffer_in)?;
+    let header = match record.header {
+        Some(header) => header,
+        None => return Err(Error::Recovery("an encrypted file has no blocks to recover")),
+    };
+    let output_len: u64 = record.blocks.iter().map(|(_, block_header, _)| block_header.raw_len as u64).sum();
+    let symbols_len = record.blocks.iter().map(|(_, block_header, _)| crate::symbols_len(block_header)).max().unwrap_or(0);
+    limits.check(buffer_in.len(), output_len, symbols_len)?;
+    let mut buffer_filtered: Vec<u8> = Vec::new();
+    let mut damaged_blocks = 0;
+    for (body_start, block_header, body_crc) in record.blocks.iter() {
+        let body = bytes_at(& buffer_in[..record.protected_len], *body_start, block_header.coded_len as usize)
+            .filter(|body| crc32fast::hash(body) == *body_crc);
+        match body.map(|body| crate::decode_block(body, block_header, dictionary)) {
+            Some(Ok(block)) => buffer_filtered.extend_from_slice(& block),
+            _ => {
+                progress!("...the block at {} is damaged, it's {} bytes are zeros", body_start, block_header.raw_len);
+                buffer_filtered.resize(buffer_filtered.len() + block_header.raw_len as usize, 0);
+                damaged_blocks += 1;
+            }
+        }
+    }
+    Ok((header.filter.decode(buffer_filtered), damaged_blocks))
+}
+
+fn parity_shards_for(data_shards: usize, percent: usize) -> usize {
+    (data_shards * percent).div_ceil(100).max(1)
+}
+
+/// The data shards of the buffer, the last one padded with zeros.
+fn split_shards(buffer_in: &[u8], shard_size: usize, data_shards: usize) -> Vec<Vec<u8>> {
+    (0..data_shards).map(|i| {
+        let mut shard = buffer_in.get(i * shard_size..).unwrap_or(&[]).iter().take(shard_size).copied().collect::<Vec<u8>>();
+        shard.resize(shard_size, 0);
+        shard
+    }).collect()
+}
+
+/// The .johnny header and the blocks, if the buffer is a .johnny file and
+/// not an encrypted one.
+fn write_block_index(buffer_in: &[u8], buffer_out: & mut Vec<u8>) {
+    let index = Header::read_from_byte_buffer(buffer_in)
+        .and_then(|header| Ok((crate::read_block_index(buffer_in, & header)?, header)));
+    let (vec_index, header) = match index {
+        Ok(index) => index,
+        Err(_) => {
+            buffer_out.push(0);
+            return;
+        }
+    };
+    buffer_out.push(1);
+    header.write_to_byte_buffer(buffer_out);
+    for (block_header, body_start) in vec_index.iter() {
+        let body = & buffer_in[*body_start..*body_start + block_header.coded_len as usize];
+        buffer_out.extend_from_slice(& (*body_start as u64).to_be_bytes());
+        block_header.write_to_byte_buffer(buffer_out);
+        buffer_out.extend_from_slice(& crc32fast::hash(body).to_be_bytes());
+    }
+}
+
+/// Reads the record from the end of the buffer, all of it's sizes are
+/// checked before the shards are read.
+fn read_record(buffer_in: &[u8]) -> Result<Record> {
+    if !has_recovery_record(buffer_in) {
+        return Err(Error::Recovery("the file has no recovery record"));
+    }
+    let cut_short = || Error::Recovery("the recovery record is cut short");
+
+    // 1. The table, before the footer, has it's own CRC32.
+    let footer = & buffer_in[buffer_in.len() - FOOTER_SIZE..];
+    let table_crc = u32::from_be_bytes([footer[0], footer[1], footer[2], footer[3]]);
+    let table_len = u32::from_be_bytes([footer[4], footer[5], footer[6], footer[7]]) as usize;
+    let table_start = (buffer_in.len() - FOOTER_SIZE).checked_sub(table_len).ok_or_else(cut_short)?;
+    let table = & buffer_in[table_start..buffer_in.len() - FOOTER_SIZE];
+    if crc32fast::hash(table) != table_crc {
+        return Err(Error::Recovery("the recovery record is damaged"));
+    }
+    let fixed = bytes_at(table, 0, 17).ok_or_else(cut_short)?;
+    if fixed[0] != RECOVERY_VERSION {
+        return Err(Error::UnsupportedVersion(fixed[0]));
+    }
+    let protected_len = u64::from_be_bytes(<[u8; 8]>::try_from(& fixed[1..9]).map_err(|_| cut_short())?) as usize;
+    let shard_size = u32::from_be_bytes([fixed[9], fixed[10], fixed[11], fixed[12]]) as usize;
+    let data_shards = u16::from_be_bytes([fixed[13], fixed[14]]) as usize;
+    let parity_shards = u16::from_be_bytes([fixed[15], fixed[16]]) as usize;
+
+    // 2. The parity shards are before the table, and the data shards hold
+    //    all of the bytes before the parity, the last one padded.
+    if data_shards == 0 || parity_shards == 0 || data_shards + parity_shards > MAX_SHARDS || shard_size == 0 {
+        return Err(Error::Recovery("the recovery record has too many or no shards"));
+    }
+    let record_start = table_start.checked_sub(parity_shards * shard_size).ok_or_else(cut_short)?;
+    if record_start != protected_len || protected_len > data_shards * shard_size || protected_len + shard_size <= data_shards * shard_size {
+        return Err(Error::Recovery("the shards of the recovery record don't fit the file"));
+    }
+
+    // 3. The CRC32 of the shards, and the copy of the blocks.
+    let num_shards = data_shards + parity_shards;
+    let crcs = bytes_at(table, 17, 4 * num_shards).ok_or_else(cut_short)?;
+    let shard_crcs: Vec<u32> = crcs.chunks(4).map(|crc| u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]])).collect();
+    let pos = 17 + 4 * num_shards;
+    let (header, blocks) = match table.get(pos) {
+        Some(1) => read_blocks(& table[pos + 1..])?,
+        Some(0) => (None, Vec::new()),
+        _ => return Err(cut_short()),
+    };
+    Ok(Record { protected_len, shard_size, data_shards, parity_shards, shard_crcs, header, blocks, record_start, table_start })
+}
+
+fn read_blocks(table: &[u8]) -> Result<(Option<Header>, Vec<BlockEntry>)> {
+    let cut_short = || Error::Recovery("the block index of the recovery record is cut short");
+    let header = Header::read_from_byte_buffer(bytes_at(table, 0, HEADER_SIZE).ok_or_else(cut_short)?)?;
+    let entries = & table[HEADER_SIZE..];
+    if entries.len() != header.num_blocks as usize * BLOCK_ENTRY_SIZE {
+        return Err(cut_short());
+    }
+    let mut blocks = Vec::with_capacity(header.num_blocks as usize);
+    for entry in entries.chunks(BLOCK_ENTRY_SIZE) {
+        let body_start = u64::from_be_bytes(<[u8; 8]>::try_from(& entry[0..8]).map_err(|_| cut_short())?) as usize;
+        let block_header = BlockHeader::read_from_byte_buffer(& entry[8..8 + BLOCK_HEADER_SIZE])?;
+        let body_crc = u32::from_be_bytes(<[u8; 4]>::try_from(& entry[8 + BLOCK_HEADER_SIZE..]).map_err(|_| cut_short())?);
+        blocks.push((body_start, block_header, body_crc));
+    }
+    Ok((Some(header), blocks))
+}
@@ -0,0 +1,90 @@
+// The detached Ed25519 signatures of the .johnny files.
+//
+// The sign action appends a signature block to the end of the file, plain or
+// encrypted. The signature covers all of the bytes before the block, the
+// .johnny header, the block headers, the tables and the coded data, or the
+// envelope of the encryption. The block is at the end so it's found without
+// reading the file, and so the file is signed without changing a byte.
+//
+//   bytes 0..64  Ed25519 signature
+//   byte  64     signature version
+//   bytes 65..69 magic "JSIG"
+//
+// The decoder stops after the last block of the .johnny header, it never
+// reads the signature block. The envelope of the encryption authenticates
+// all of it's bytes, the signature block is removed before it's decrypted.
+// The signing key file has the 32 bytes of the Ed25519 secret key, and the
+// verifying key file the 32 bytes of the public key, raw or in hexadecimal.
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use chacha20poly1305::aead::OsRng;
+
+use crate::encryption::KEY_SIZE;
+use crate::error::{Error, Result};
+
+pub const MAGIC: &[u8; 4] = b"JSIG";
+pub const SIGNATURE_VERSION: u8 = 1;
+pub const SIGNATURE_BLOCK_SIZE: usize = 69;
+
+const SIGNATURE_SIZE: usize = 64;
+
+/// A new signing key and it's verifying key.
+pub fn generate_keys() -> ([u8; KEY_SIZE], [u8; KEY_SIZE]) {
+    let signing_key = SigningKey::generate(& mut OsRng);
+    (signing_key.to_bytes(), signing_key.verifying_key().to_bytes())
+}
+
+/// The verifying key of a signing key.
+pub fn verifying_key(signing_key: &[u8; KEY_SIZE]) -> [u8; KEY_SIZE] {
+    SigningKey::from_bytes(signing_key).verifying_key().to_bytes()
+}
+
+/// True if the buffer ends with a signature block.
+pub fn is_signed(buffer_in: &[u8]) -> bool {
+    buffer_in.len() >= SIGNATURE_BLOCK_SIZE && buffer_in.ends_with(MAGIC)
+}
+
+/// The bytes before the signature block, or all of the buffer if it isn't
+/// signed.
+pub fn unsigned_part(buffer_in: &[u8]) -> &[u8] {
+    if is_signed(buffer_in) {
+        & buffer_in[..buffer_in.len() - SIGNATURE_BLOCK_SIZE]
+    } else {
+        buffer_in
+    }
+}
+
+/// The file with the signature block of the signing key at the end.
+pub fn sign(buffer_in: &[u8], signing_key: &[u8; KEY_SIZE]) -> Result<Vec<u8>> {
+    if is_signed(buffer_in) {
+        return Err(Error::Signature("the file is already signed"));
+    }
+    let signature = SigningKey::from_bytes(signing_key).sign(buffer_in);
+    let mut buffer_out: Vec<u8> = Vec::with_capacity(buffer_in.len() + SIGNATURE_BLOCK_SIZE);
+    buffer_out.extend_from_slice(buffer_in);
+    buffer_out.extend_from_slice(& signature.to_bytes());
+    buffer_out.push(SIGNATURE_VERSION);
+    buffer_out.extend_from_slice(MAGIC);
+    Ok(buffer_out)
+}
+
+/// Checks the signature block with the verifying key, and returns the
+/// signed bytes before it.
+pub fn verify<'a>(buffer_in: &'a [u8], verifying_key: &[u8; KEY_SIZE]) -> Result<&'a [u8]> {
+    if !is_signed(buffer_in) {
+        return Err(Error::Signature("the file isn't signed"));
+    }
+    let (signed, block) = buffer_in.split_at(buffer_in.len() - SIGNATURE_BLOCK_SIZE);
+    if block[SIGNATURE_SIZE] != SIGNATURE_VERSION {
+        return Err(Error::UnsupportedVersion(block[SIGNATURE_SIZE]));
+    }
+    let verifying_key = VerifyingKey::from_bytes(verifying_key)
+        .map_err(|_| Error::Signature("the verifying key isn't a valid Ed25519 public key"))?;
+    let mut signature_bytes = [0u8; SIGNATURE_SIZE];
+    signature_bytes.copy_from_slice(& block[..SIGNATURE_SIZE]);
+    // The strict verification rejects the weak keys and the malleable
+    // signatures.
+    verifying_key.verify_strict(signed, & Signature::from_bytes(& signature_bytes))
+        .map_err(|_| Error::Signature("the signature isn't of this key, or the file was changed"))?;
+    Ok(signed)
+}
@@ -0,0 +1,254 @@
+// Interleaved bit streams of Huffman codes, like the 4 streams of huff0.
+//
+// The symbol i of the message goes to the stream i % N, so the decoder can
+// decode one symbol of each stream in turn and the CPU can keep the N
+// streams in flight, because each stream only depends on itself. After the
+// 8 bytes of the number of symbols comes:
+//
+//   1 byte    number of streams N
+//   jump      the number of bytes of each stream, except the last one, in
+//             4 bytes big endian
+//   the bytes of the streams, one after the other
+//
+// The decoder looks up TABLE_BITS bits at a time in a table with the symbol
+// and the length of the code, the longer codes are decoded bit by bit.
+
+use hashbrown::HashMap;
+
+use crate::alphabet::{Symbol, SymbolType};
+use crate::error::{bytes_at, Error, Result};
+
+pub const MAX_STREAMS: usize = 16;
+
+/// The codes are read in a 64 bit accumulator.
+pub const MAX_CODE_LEN: u32 = 64;
+
+/// Number of bits of the decoding table, 2^TABLE_BITS entries.
+const TABLE_BITS: u32 = 11;
+
+/// Marks the entries of the decoding table of the codes longer than the table.
+const LONG_CODE: u32 = u32::MAX;
+
+/// The code of each symbol as a number and it's length in bits, from the
+/// strings of '0' and '1'.
+pub fn code_values(map_encoding: & HashMap< Symbol, String >) -> Vec<(Symbol, u64, u32)> {
+    map_encoding.iter()
+        .map(|(symbol, code)| {
+            let value = code.bytes().fold(0_u64, |value, c| value << 1 | (c == b'1') as u64);
+            (*symbol, value, code.len() as u32)
+        })
+        .collect()
+}
+
+/// Encodes the message in num_streams interleaved streams and writes the
+/// number of streams, the jump table and the streams. The number of streams
+/// is kept in 1 to MAX_STREAMS, the ones the decoder reads.
+pub fn encode_streams<S: SymbolType>(vec_codes: &[(Symbol, u64, u32)], buffer_in: &[S], num_streams: usize, buffer_out: & mut Vec<u8>) {
+    let num_streams = num_streams.clamp(1, MAX_STREAMS);
+
+    // The code of each symbol, indexed by the symbol. The table is made from
+    // the symbols of the message, every symbol has a code.
+    let max_symbol = vec_codes.iter().map(|(symbol, _, _)| *symbol).max().unwrap_or(0);
+    let mut map_codes: Vec<Option<(u64, u32)>> = vec![None; max_symbol as usize + 1];
+    for (symbol, value, len) in vec_codes {
+        map_codes[*symbol as usize] = Some((*value, *len));
+    }
+
+    let mut vec_writers: Vec<BitWriter> = (0..num_streams).map(|_| BitWriter::new()).collect();
+    for (i, symbol) in buffer_in.iter().enumerate() {
+        let (value, len) = map_codes.get(symbol.to_symbol() as usize)
+            .copied()
+            .flatten()
+            .unwrap_or_else(|| panic!("...the symbol {} has no Huffman code", symbol.to_symbol()));
+        vec_writers[i % num_streams].write_bits(value, len);
+    }
+    let vec_streams: Vec<Vec<u8>> = vec_writers.into_iter().map(BitWriter::finish).collect();
+
+    buffer_out.push(num_streams as u8);
+    for stream in & vec_streams[..num_streams - 1] {
+        buffer_out.extend_from_slice(& (stream.len() as u32).to_be_bytes());
+    }
+    for stream in & vec_streams {
+        buffer_out.extend_from_slice(stream);
+    }
+}
+
+/// Decodes symbol_counter symbols from the interleaved streams at the start
+/// of buffer_in, the number of streams and the jump table.
+pub fn decode_streams<S: SymbolType>(vec_codes: &[(Symbol, u64, u32)], buffer_in: &[u8], symbol_counter: usize, buffer_out: & mut Vec<S>) -> Result<()> {
+    let num_streams = match buffer_in.first() {
+        Some(&n) if (1..=MAX_STREAMS as u8).contains(&n) => n as usize,
+        Some(_) => return Err(Error::BadTable("invalid number of Huffman streams")),
+        None    => return Err(Error::PayloadUnderrun("the Huffman streams are missing")),
+    };
+    let mut pos = 1 + 4 * (num_streams - 1);
+    let mut vec_readers: Vec<BitReader> = Vec::with_capacity(num_streams);
+    for stream in 0..num_streams {
+        let stream_end = if stream + 1 == num_streams {
+            buffer_in.len()
+        } else {
+            let jump = bytes_at(buffer_in, 1 + 4 * stream, 4)
+                .ok_or(Error::PayloadUnderrun("the jump table of the streams is cut short"))?;
+            pos + u32::from_be_bytes([jump[0], jump[1], jump[2], jump[3]]) as usize
+        };
+        let stream_bytes = buffer_in.get(pos..stream_end)
+            .ok_or(Error::PayloadUnderrun("a Huffman stream is cut short"))?;
+        vec_readers.push(BitReader::new(stream_bytes));
+        pos = stream_end;
+    }
+
+    let table = DecodeTable::new(vec_codes)?;
+    buffer_out.reserve(symbol_counter);
+    // One symbol of each stream in turn, and then the symbols of the first
+    // streams that are left.
+    for _ in 0..symbol_counter / num_streams {
+        for reader in vec_readers.iter_mut() {
+            buffer_out.push(S::from_symbol(table.decode(reader)?));
+        }
+    }
+    for reader in vec_readers.iter_mut().take(symbol_counter % num_streams) {
+        buffer_out.push(S::from_symbol(table.decode(reader)?));
+    }
+    // After the end of a stream the reader gives zeros, the symbols decoded
+    // from them aren't in the file.
+    if vec_readers.iter().any(BitReader::is_overrun) {
+        return Err(Error::PayloadUnderrun("the Huffman streams end before the last symbol"));
+    }
+    Ok(())
+}
+
+/// The decoding table, for each value of the next table_bits bits the symbol
+/// and the length of it's code.
+struct DecodeTable {
+    table_bits:     u32,
+    max_len:        u32,
+    vec_table:      Vec<(Symbol, u32)>,
+    map_long_codes: HashMap< (u32, u64), Symbol >,
+}
+
+impl DecodeTable {
+
+    fn new(vec_codes: &[(Symbol, u64, u32)]) -> Result<DecodeTable> {
+        let max_len = vec_codes.iter().map(|(_, _, len)| *len).max().unwrap_or(0);
+        let table_bits = max_len.min(TABLE_BITS);
+        let mut vec_table: Vec<(Symbol, u32)> = vec![(0, LONG_CODE); 1 << table_bits];
+        let mut map_long_codes = HashMap::new();
+        for (symbol, value, len) in vec_codes {
+            if *len > MAX_CODE_LEN {
+                return Err(Error::BadTable("the lengths of the Huffman codes aren't valid"));
+            }
+            if *len <= table_bits {
+                // All the entries that start with the code.
+                let shift = table_bits - len;
+                let first = (*value as usize) << shift;
+                for entry in & mut vec_table[first..first + (1 << shift)] {
+                    *entry = (*symbol, *len);
+                }
+            } else {
+                map_long_codes.insert((*len, *value), *symbol);
+            }
+        }
+        Ok(DecodeTable { table_bits, max_len, vec_table, map_long_codes })
+    }
+
+    fn decode(&self, reader: & mut BitReader) -> Result<Symbol> {
+        reader.refill();
+        let (symbol, len) = self.vec_table[reader.peek(self.table_bits) as usize];
+        if len != LONG_CODE {
+            reader.consume(len);
+            return Ok(symbol);
+        }
+        // The code is longer than the table, one bit at a time.
+        let mut value = reader.peek(self.table_bits);
+        reader.consume(self.table_bits);
+        let mut len = self.table_bits;
+        while len < self.max_len {
+            reader.refill();
+            value = value << 1 | reader.peek(1);
+            reader.consume(1);
+            len += 1;
+            if let Some(symbol) = self.map_long_codes.get(& (len, value)) {
+                return Ok(*symbol);
+            }
+        }
+        Err(Error::BadTable("invalid Huffman code in the .johnny file"))
+    }
+}
+
+/// Writes the bits from the most significant, the first bit is the highest
+/// bit of the first byte.
+struct BitWriter {
+    acc:        u64,
+    nb_acc:     u32,
+    buffer_out: Vec<u8>,
+}
+
+impl BitWriter {
+
+    fn new() -> BitWriter {
+        BitWriter { acc: 0, nb_acc: 0, buffer_out: Vec::new() }
+    }
+
+    fn write_bits(& mut self, value: u64, nb_bits: u32) {
+        if nb_bits > 32 {
+            self.write_bits(value >> 32, nb_bits - 32);
+            self.write_bits(value & 0xFFFF_FFFF, 32);
+            return;
+        }
+        self.acc = self.acc << nb_bits | value;
+        self.nb_acc += nb_bits;
+        while self.nb_acc >= 8 {
+            self.nb_acc -= 8;
+            self.buffer_out.push((self.acc >> self.nb_acc) as u8);
+        }
+        self.acc &= (1 << self.nb_acc) - 1;
+    }
+
+    /// Writes the last byte, filled with zeros.
+    fn finish(mut self) -> Vec<u8> {
+        if self.nb_acc > 0 {
+            self.buffer_out.push((self.acc << (8 - self.nb_acc)) as u8);
+        }
+        self.buffer_out
+    }
+}
+
+/// Reads the bits from the most significant, keeps the next bits at the top
+/// of a 64 bit accumulator. After the end of the stream it reads zeros.
+struct BitReader<'a> {
+    buffer_in: &'a [u8],
+    pos:       usize,
+    acc:       u64,
+    nb_acc:    u32,
+}
+
+impl<'a> BitReader<'a> {
+
+    fn new(buffer_in: &'a [u8]) -> BitReader<'a> {
+        BitReader { buffer_in, pos: 0, acc: 0, nb_acc: 0 }
+    }
+
+    /// Fills the accumulator with at least 57 bits.
+    fn refill(& mut self) {
+        while self.nb_acc <= 56 {
+            let byte = *self.buffer_in.get(self.pos).unwrap_or(&0);
+            self.acc |= (byte as u64) << (56 - self.nb_acc);
+            self.nb_acc += 8;
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self, nb_bits: u32) -> u64 {
+        if nb_bits == 0 { 0 } else { self.acc >> (64 - nb_bits) }
+    }
+
+    fn consume(& mut self, nb_bits: u32) {
+        self.acc = if nb_bits == 64 { 0 } else { self.acc << nb_bits };
+        self.nb_acc -= nb_bits;
+    }
+
+    /// If more bits were consumed than the stream has.
+    fn is_overrun(&self) -> bool {
+        self.pos * 8 - self.nb_acc as usize > self.buffer_in.len() * 8
+    }
+}
@@ -0,0 +1,212 @@
+// The command line, run on files of a temporary directory.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// An empty directory for the files of the test.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("huffman_codes_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(& dir);
+    fs::create_dir_all(& dir).unwrap();
+    dir
+}
+
+fn huffman_codes(args: &[&str]) -> i32 {
+    Command::new(env!("CARGO_BIN_EXE_huffman_codes"))
+        .args(args)
+        .output()
+        .expect("...the command line runs.")
+        .status
+        .code()
+        .unwrap_or(-1)
+}
+
+#[test]
+fn output_is_renamed_into_place_without_temporary_files() {
+    let dir = test_dir("output");
+    let message: Vec<u8> = b"abracadabra ".iter().cycle().take(10_000).copied().collect();
+    let filename = dir.join("message.txt");
+    fs::write(& filename, & message).unwrap();
+    // An old .johnny file is replaced.
+    fs::write(dir.join("message.txt.johnny"), b"old").unwrap();
+
+    assert_eq!(huffman_codes(&["compress", filename.to_str().unwrap()]), 0);
+    fs::remove_file(& filename).unwrap();
+    assert_eq!(huffman_codes(&["decompress", dir.join("message.txt.johnny").to_str().unwrap()]), 0);
+    assert!(fs::read(& filename).unwrap() == message);
+
+    let mut vec_names: Vec<String> = fs::read_dir(& dir).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    vec_names.sort();
+    assert_eq!(vec_names, ["message.txt", "message.txt.johnny"]);
+    fs::remove_dir_all(& dir).unwrap();
+}
+
+/// A replaced file keeps it's permissions.
+#[cfg(unix)]
+#[test]
+fn replaced_output_keeps_the_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = test_dir("permissions");
+    let filename = dir.join("private.txt");
+    fs::write(& filename, b"private private private").unwrap();
+    let johnny_name = dir.join("private.txt.johnny");
+    fs::write(& johnny_name, b"old").unwrap();
+    fs::set_permissions(& johnny_name, fs::Permissions::from_mode(0o600)).unwrap();
+
+    assert_eq!(huffman_codes(&["compress", filename.to_str().unwrap()]), 0);
+    assert_eq!(fs::metadata(& johnny_name).unwrap().permissions().mode() & 0o777, 0o600);
+    assert_eq!(fs::read_dir(& dir).unwrap().count(), 2);
+    fs::remove_dir_all(& dir).unwrap();
+}
+
+#[test]
+fn failed_decompression_leaves_no_file() {
+    let dir = test_dir("corrupt");
+    let filename = dir.join("corrupt.bin.johnny");
+    fs::write(& filename, b"JHNY not really").unwrap();
+    assert_ne!(huffman_codes(&["decompress", filename.to_str().unwrap()]), 0);
+    assert_eq!(fs::read_dir(& dir).unwrap().count(), 1);
+    fs::remove_dir_all(& dir).unwrap();
+}
+
+/// A named pipe has no length, it's read until the writer closes it.
+#[cfg(unix)]
+#[test]
+fn pipe_is_read_until_the_end() {
+    let dir = test_dir("pipe");
+    let pipe_name = dir.join("pipe");
+    let status = Command::new("mkfifo").arg(& pipe_name).status().expect("...mkfifo runs.");
+    assert!(status.success());
+
+    // More than the buffer of a pipe, in several writes.
+    let message: Vec<u8> = (0..200_000_u32).map(|i| (i % 251) as u8).collect();
+    let writer = {
+        let (pipe_name, message) = (pipe_name.clone(), message.clone());
+        std::thread::spawn(move || {
+            use std::io::Write;
+            let mut pipe = fs::OpenOptions::new().write(true).open(& pipe_name).unwrap();
+            for chunk in message.chunks(7000) {
+                pipe.write_all(chunk).unwrap();
+            }
+        })
+    };
+    assert_eq!(huffman_codes(&["compress", pipe_name.to_str().unwrap()]), 0);
+    writer.join().unwrap();
+
+    fs::remove_file(& pipe_name).unwrap();
+    assert_eq!(huffman_codes(&["decompress", dir.join("pipe.johnny").to_str().unwrap()]), 0);
+    assert!(fs::read(& pipe_name).unwrap() == message);
+    fs::remove_dir_all(& dir).unwrap();
+}
+
+/// A regular file is mapped, unless --no-mmap reads it for the files that
+/// can be truncated while they are mapped.
+#[test]
+fn no_mmap_reads_the_input_file() {
+    let dir = test_dir("mapped");
+    let filename = dir.join("message.txt");
+    fs::write(& filename, b"mapped or read ".repeat(1000)).unwrap();
+    let is_mapped = |extra_args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_huffman_codes"))
+            .arg("compress")
+            .args(extra_args)
+            .arg(& filename)
+            .output()
+            .expect("...the command line runs.");
+        assert!(output.status.success());
+        String::from_utf8_lossy(& output.stdout).contains("...memory mapping")
+    };
+    assert!(is_mapped(& []));
+    assert!(!is_mapped(& ["--no-mmap"]));
+    fs::remove_dir_all(& dir).unwrap();
+}
+
+#[test]
+fn encrypted_file_needs_the_password() {
+    let dir = test_dir("encrypted");
+    let message: Vec<u8> = b"port = 8443\n".iter().cycle().take(3000).copied().collect();
+    let filename = dir.join("bundle.conf");
+    let johnny_name = dir.join("bundle.conf.johnny");
+    fs::write(& filename, & message).unwrap();
+    fs::write(dir.join("password.txt"), b"correct horse\n").unwrap();
+    fs::write(dir.join("wrong.txt"), b"battery staple\n").unwrap();
+    let password = dir.join("password.txt");
+    let wrong = dir.join("wrong.txt");
+
+    assert_eq!(huffman_codes(&["compress", "--password-file", password.to_str().unwrap(), filename.to_str().unwrap()]), 0);
+    fs::remove_file(& filename).unwrap();
+    assert_eq!(huffman_codes(&["decompress", johnny_name.to_str().unwrap()]), 14);
+    assert_eq!(huffman_codes(&["decompress", "--password-file", wrong.to_str().unwrap(), johnny_name.to_str().unwrap()]), 15);
+    assert!(!filename.exists());
+    assert_eq!(huffman_codes(&["decompress", "--password-file", password.to_str().unwrap(), johnny_name.to_str().unwrap()]), 0);
+    assert!(fs::read(& filename).unwrap() == message);
+    fs::remove_dir_all(& dir).unwrap();
+}
+
+#[test]
+fn signed_file_is_verified_with_the_verifying_key() {
+    let dir = test_dir("signed");
+    let filename = dir.join("bundle.conf");
+    let johnny_name = dir.join("bundle.conf.johnny");
+    let signing_key = dir.join("signing.key");
+    let verifying_key = dir.join("signing.key.pub");
+    fs::write(& filename, b"port = 8443\n".repeat(100)).unwrap();
+
+    assert_eq!(huffman_codes(&["keygen", signing_key.to_str().unwrap()]), 0);
+    // A key file is never replaced.
+    assert_eq!(huffman_codes(&["keygen", signing_key.to_str().unwrap()]), 2);
+    assert_eq!(huffman_codes(&["compress", filename.to_str().unwrap()]), 0);
+    assert_eq!(huffman_codes(&["verify", "--key-file", verifying_key.to_str().unwrap(), johnny_name.to_str().unwrap()]), 16);
+    assert_eq!(huffman_codes(&["sign", "--key-file", signing_key.to_str().unwrap(), johnny_name.to_str().unwrap()]), 0);
+    assert_eq!(huffman_codes(&["verify", "--key-file", verifying_key.to_str().unwrap(), johnny_name.to_str().unwrap()]), 0);
+
+    let mut buffer_signed = fs::read(& johnny_name).unwrap();
+    buffer_signed[20] ^= 0x01;
+    fs::write(& johnny_name, & buffer_signed).unwrap();
+    assert_eq!(huffman_codes(&["verify", "--key-file", verifying_key.to_str().unwrap(), johnny_name.to_str().unwrap()]), 16);
+    fs::remove_dir_all(& dir).unwrap();
+}
+
+/// Only the user can read the signing key, and an existing file with it's
+/// name isn't opened.
+#[cfg(unix)]
+#[test]
+fn signing_key_is_private() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = test_dir("keygen");
+    let signing_key = dir.join("signing.key");
+    assert_eq!(huffman_codes(&["keygen", signing_key.to_str().unwrap()]), 0);
+    assert_eq!(fs::metadata(& signing_key).unwrap().permissions().mode() & 0o777, 0o600);
+
+    let planted_key = dir.join("planted.key");
+    fs::write(& planted_key, b"planted").unwrap();
+    assert_eq!(huffman_codes(&["keygen", planted_key.to_str().unwrap()]), 2);
+    assert_eq!(fs::read(& planted_key).unwrap(), b"planted");
+    fs::remove_dir_all(& dir).unwrap();
+}
+
+#[test]
+fn damaged_archive_is_repaired_in_place() {
+    let dir = test_dir("repair");
+    let message: Vec<u8> = (0..100_000_u32).map(|i| (i % 13) as u8).collect();
+    let filename = dir.join("archive.tar");
+    let johnny_name = dir.join("archive.tar.johnny");
+    fs::write(& filename, & message).unwrap();
+    assert_eq!(huffman_codes(&["compress", "--recovery", "10", filename.to_str().unwrap()]), 0);
+    fs::remove_file(& filename).unwrap();
+
+    let mut buffer_damaged = fs::read(& johnny_name).unwrap();
+    buffer_damaged[40] ^= 0xFF;
+    fs::write(& johnny_name, & buffer_damaged).unwrap();
+    assert_eq!(huffman_codes(&["repair", johnny_name.to_str().unwrap()]), 0);
+    assert_eq!(huffman_codes(&["decompress", johnny_name.to_str().unwrap()]), 0);
+    assert!(fs::read(& filename).unwrap() == message);
+
+    // A file without a record can't be repaired.
+    assert_eq!(huffman_codes(&["compress", filename.to_str().unwrap()]), 0);
+    assert_eq!(huffman_codes(&["repair", johnny_name.to_str().unwrap()]), 17);
+    fs::remove_dir_all(& dir).unwrap();
+}
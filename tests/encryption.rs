@@ -0,0 +1,116 @@
+// The encrypted .johnny files, authenticated before anything is decoded.
+
+use huffman_codes::encryption::{self, KdfParams, Secret, ENVELOPE_HEADER_SIZE};
+use huffman_codes::error::Error;
+use huffman_codes::limits::Limits;
+use huffman_codes::CompressOptions;
+
+/// Argon2id with 64 KiB and one iteration, for fast tests.
+const FAST_KDF: KdfParams = KdfParams { memory_kib: 64, iterations: 1, lanes: 1 };
+
+fn compressed_message() -> (Vec<u8>, Vec<u8>) {
+    huffman_codes::set_quiet(true);
+    let message: Vec<u8> = b"server = edge-01\nport = 8443\n".iter().cycle().take(5000).copied().collect();
+    let buffer_johnny = huffman_codes::compress_buffer(& message, & CompressOptions::default());
+    (message, buffer_johnny)
+}
+
+fn password(text: &str) -> Secret {
+    Secret::Password(text.as_bytes().to_vec())
+}
+
+#[test]
+fn password_and_key_round_trip() {
+    let (message, buffer_johnny) = compressed_message();
+    let secrets = [password("correct horse"), Secret::Key([7; 32])];
+    for secret in secrets.iter() {
+        let buffer_encrypted = encryption::encrypt_with_params(& buffer_johnny, secret, & FAST_KDF).unwrap();
+        assert!(encryption::is_encrypted(& buffer_encrypted));
+        let buffer_decrypted = encryption::decrypt(& buffer_encrypted, secret).unwrap();
+        assert!(buffer_decrypted == buffer_johnny);
+        assert!(huffman_codes::decompress_buffer(& buffer_decrypted, 1).unwrap() == message);
+    }
+}
+
+#[test]
+fn same_password_gives_another_ciphertext() {
+    let (_, buffer_johnny) = compressed_message();
+    let secret = password("correct horse");
+    let first = encryption::encrypt_with_params(& buffer_johnny, & secret, & FAST_KDF).unwrap();
+    let second = encryption::encrypt_with_params(& buffer_johnny, & secret, & FAST_KDF).unwrap();
+    assert!(first != second);
+}
+
+#[test]
+fn any_changed_byte_fails_the_authentication() {
+    let (_, buffer_johnny) = compressed_message();
+    let secret = password("correct horse");
+    let buffer_encrypted = encryption::encrypt_with_params(& buffer_johnny, & secret, & FAST_KDF).unwrap();
+    // The salt and the nonce of the header, the ciphertext and the tag.
+    for &pos in [20, 35, ENVELOPE_HEADER_SIZE, buffer_encrypted.len() / 2, buffer_encrypted.len() - 1].iter() {
+        let mut buffer_changed = buffer_encrypted.clone();
+        buffer_changed[pos] ^= 0x01;
+        match encryption::decrypt(& buffer_changed, & secret) {
+            Err(error @ Error::Authentication(_)) => assert_eq!(error.exit_code(), 15),
+            other => panic!("...the byte {} changed gives {:?}", pos, other.map(|buffer_out| buffer_out.len())),
+        }
+    }
+}
+
+#[test]
+fn wrong_secret_is_rejected() {
+    let (_, buffer_johnny) = compressed_message();
+    let buffer_encrypted = encryption::encrypt_with_params(& buffer_johnny, & password("correct horse"), & FAST_KDF).unwrap();
+    assert!(matches!(encryption::decrypt(& buffer_encrypted, & password("battery staple")), Err(Error::Authentication(_))));
+    assert!(matches!(encryption::decrypt(& buffer_encrypted, & Secret::Key([7; 32])), Err(Error::Encrypted(_))));
+}
+
+#[test]
+fn encrypted_file_is_never_decoded_without_the_secret() {
+    let (_, buffer_johnny) = compressed_message();
+    let buffer_encrypted = encryption::encrypt_with_params(& buffer_johnny, & Secret::Key([7; 32]), & FAST_KDF).unwrap();
+    match huffman_codes::decompress_buffer(& buffer_encrypted, 1) {
+        Err(error @ Error::Encrypted(_)) => assert_eq!(error.exit_code(), 14),
+        other => panic!("...the encrypted file gives {:?}", other.map(|buffer_out| buffer_out.len())),
+    }
+    // With a secret, a plain .johnny file can't be authenticated.
+    assert!(matches!(encryption::decrypt(& buffer_johnny, & Secret::Key([7; 32])), Err(Error::Authentication(_))));
+}
+
+#[test]
+fn crafted_kdf_parameters_are_rejected() {
+    let (_, buffer_johnny) = compressed_message();
+    let secret = password("correct horse");
+    let mut buffer_encrypted = encryption::encrypt_with_params(& buffer_johnny, & secret, & FAST_KDF).unwrap();
+    // 4 TiB of memory for the key derivation.
+    buffer_encrypted[6..10].copy_from_slice(& u32::MAX.to_be_bytes());
+    assert!(matches!(encryption::decrypt(& buffer_encrypted, & secret), Err(Error::BadHeader(_))));
+    assert!(matches!(encryption::decrypt(& buffer_encrypted[..30], & secret), Err(Error::TruncatedHeader(_))));
+}
+
+#[test]
+fn kdf_memory_is_checked_with_the_limits() {
+    let (_, buffer_johnny) = compressed_message();
+    let secret = password("correct horse");
+    let mut buffer_encrypted = encryption::encrypt_with_params(& buffer_johnny, & secret, & FAST_KDF).unwrap();
+    // 200 MiB for the key derivation, under the hard cap but over the limit.
+    buffer_encrypted[6..10].copy_from_slice(& (200 * 1024u32).to_be_bytes());
+    let limits = Limits { max_memory: 16 * 1024 * 1024, ..Limits::NONE };
+    match encryption::decrypt_with_limits(& buffer_encrypted, & secret, & limits) {
+        Err(error @ Error::LimitExceeded { limit: "memory", .. }) => assert_eq!(error.exit_code(), 12),
+        other => panic!("...the key derivation over the memory limit gives {:?}", other.map(|buffer_out| buffer_out.len())),
+    }
+}
+
+#[test]
+fn key_file_is_raw_or_hexadecimal() {
+    let hex = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f\n";
+    let raw: Vec<u8> = (0..32).collect();
+    for bytes in [hex.as_bytes(), & raw[..]].iter() {
+        match Secret::key_from_bytes(bytes) {
+            Some(Secret::Key(key)) => assert!(key[..] == raw[..]),
+            other => panic!("...the key file gives {:?}", other),
+        }
+    }
+    assert!(Secret::key_from_bytes(b"not a key").is_none());
+}
@@ -0,0 +1,103 @@
+// The limits of the decompression, checked before the blocks are decoded.
+
+use huffman_codes::alphabet::Alphabet;
+use huffman_codes::coder::Coder;
+use huffman_codes::error::Error;
+use huffman_codes::limits::Limits;
+use huffman_codes::CompressOptions;
+
+/// The .johnny file of 10 MiB of zeros, a few bytes in a single run.
+fn compressed_zeros() -> Vec<u8> {
+    huffman_codes::set_quiet(true);
+    let options = CompressOptions { block_size: 16 * 1024 * 1024, ..CompressOptions::default() };
+    huffman_codes::compress_buffer(& vec![0; 10 * 1024 * 1024], & options)
+}
+
+fn decompress(buffer_johnny: &[u8], limits: Limits) -> Result<Vec<u8>, Error> {
+    huffman_codes::decompress_buffer_with_limits(buffer_johnny, 1, None, & limits)
+}
+
+#[test]
+fn message_within_the_limits_decompresses() {
+    let buffer_johnny = compressed_zeros();
+    let limits = Limits { max_output: 10 * 1024 * 1024, max_ratio: 10 * 1024 * 1024, max_memory: 64 * 1024 * 1024 };
+    assert_eq!(decompress(& buffer_johnny, limits).unwrap().len(), 10 * 1024 * 1024);
+}
+
+#[test]
+fn each_limit_is_enforced() {
+    let buffer_johnny = compressed_zeros();
+    let vec_limits = [
+        Limits { max_output: 1024 * 1024, ..Limits::NONE },
+        Limits { max_ratio: 1000, ..Limits::NONE },
+        Limits { max_memory: 16 * 1024 * 1024, ..Limits::NONE },
+    ];
+    for limits in vec_limits.iter() {
+        match decompress(& buffer_johnny, *limits) {
+            Err(error @ Error::LimitExceeded { .. }) => assert_eq!(error.exit_code(), 12),
+            other => panic!("...{:?} gives {:?}", limits, other.map(|buffer_out| buffer_out.len())),
+        }
+    }
+}
+
+#[test]
+fn crafted_block_size_is_rejected_before_decoding() {
+    // The block header after the 15 bytes of the .johnny header, it's raw
+    // length in the bytes 2 to 6 claims 4 GiB.
+    let mut buffer_johnny = compressed_zeros();
+    buffer_johnny[17..21].copy_from_slice(& u32::MAX.to_be_bytes());
+    let limits = Limits { max_output: 1024 * 1024 * 1024, ..Limits::NONE };
+    match decompress(& buffer_johnny, limits) {
+        Err(Error::LimitExceeded { limit, value, max }) => {
+            assert_eq!(limit, "output size");
+            assert_eq!(value, u32::MAX as u64);
+            assert_eq!(max, 1024 * 1024 * 1024);
+        }
+        other => panic!("...the crafted file gives {:?}", other.map(|buffer_out| buffer_out.len())),
+    }
+}
+
+/// A block of the words alphabet with a single word of 255 bytes, and a run
+/// of 2 million symbols of it, 510 MB for a file of a few hundred bytes.
+fn crafted_words_block() -> Vec<u8> {
+    let raw_len: u32 = 2_000_000;
+    let mut buffer_body: Vec<u8> = Vec::new();
+    // The dictionary of the words alphabet, one word without a prefix.
+    buffer_body.extend_from_slice(& 1_u32.to_be_bytes());
+    buffer_body.extend_from_slice(& [0, 255]);
+    buffer_body.extend_from_slice(& [b'a'; 255]);
+    // The Huffman table of a run of the word, the symbol 256 in 2 bytes with
+    // the empty code, and the number of symbols.
+    buffer_body.extend_from_slice(& 8_u32.to_be_bytes());
+    buffer_body.extend_from_slice(& [2, 0x01, 0x00, 0]);
+    buffer_body.extend_from_slice(& (raw_len as u64).to_be_bytes());
+
+    // The .johnny header of a file of one block, and the block header.
+    let mut buffer_johnny = compressed_zeros()[..15].to_vec();
+    buffer_johnny.push(Alphabet::Words.to_header_byte());
+    buffer_johnny.push(Coder::Huffman.to_header_byte());
+    buffer_johnny.extend_from_slice(& raw_len.to_be_bytes());
+    buffer_johnny.extend_from_slice(& (buffer_body.len() as u32).to_be_bytes());
+    buffer_johnny.extend_from_slice(& buffer_body);
+    buffer_johnny
+}
+
+#[test]
+fn crafted_words_block_stops_at_the_length_of_the_block() {
+    let buffer_johnny = crafted_words_block();
+    match decompress(& buffer_johnny, Limits::NONE) {
+        Err(error @ Error::PayloadUnderrun(_)) => assert_eq!(error.exit_code(), 9),
+        other => panic!("...the crafted file gives {:?}", other.map(|buffer_out| buffer_out.len())),
+    }
+}
+
+#[test]
+fn symbols_of_the_words_alphabet_count_in_the_memory() {
+    // The 2 MB of the block and of the message fit, the 8 MB of the u32
+    // symbols don't.
+    let buffer_johnny = crafted_words_block();
+    match decompress(& buffer_johnny, Limits { max_memory: 8 * 1024 * 1024, ..Limits::NONE }) {
+        Err(Error::LimitExceeded { limit, .. }) => assert_eq!(limit, "memory"),
+        other => panic!("...the crafted file gives {:?}", other.map(|buffer_out| buffer_out.len())),
+    }
+}
@@ -0,0 +1,119 @@
+// The recovery records, the repair of the damaged shards and the partial
+// recovery of the blocks that are intact.
+
+use huffman_codes::encryption::{self, KdfParams, Secret};
+use huffman_codes::error::Error;
+use huffman_codes::limits::Limits;
+use huffman_codes::recovery;
+use huffman_codes::CompressOptions;
+
+/// A message of 16 blocks of 1 KiB.
+fn compressed_message() -> (Vec<u8>, Vec<u8>) {
+    huffman_codes::set_quiet(true);
+    let message: Vec<u8> = (0..16 * 1024_u32).map(|i| (i % 7 + (i / 1024) % 5) as u8).collect();
+    let options = CompressOptions { block_size: 1024, ..CompressOptions::default() };
+    let buffer_johnny = huffman_codes::compress_buffer(& message, & options);
+    (message, buffer_johnny)
+}
+
+#[test]
+fn intact_file_needs_no_repair() {
+    let (message, buffer_johnny) = compressed_message();
+    let buffer_recovery = recovery::add_recovery_record(& buffer_johnny, 10);
+    assert!(recovery::has_recovery_record(& buffer_recovery));
+    assert!(recovery::without_recovery_record(& buffer_recovery) == & buffer_johnny[..]);
+    // The decoder doesn't read after the last block.
+    assert!(huffman_codes::decompress_buffer(& buffer_recovery, 1).unwrap() == message);
+    let (buffer_repaired, damaged) = recovery::repair(& buffer_recovery).unwrap();
+    assert_eq!(damaged, 0);
+    assert!(buffer_repaired == buffer_recovery);
+}
+
+#[test]
+fn damaged_shards_are_rebuilt() {
+    let (message, buffer_johnny) = compressed_message();
+    for &percent in [1, 10, 50, 100].iter() {
+        let buffer_recovery = recovery::add_recovery_record(& buffer_johnny, percent);
+        // A byte of the header, of the last data shard and of the parity.
+        let mut buffer_damaged = buffer_recovery.clone();
+        for &pos in [5, buffer_johnny.len() - 1, buffer_johnny.len() + 3].iter() {
+            buffer_damaged[pos] ^= 0xFF;
+        }
+        match recovery::repair(& buffer_damaged) {
+            Ok((buffer_repaired, damaged)) => {
+                assert!(buffer_repaired == buffer_recovery);
+                assert!(huffman_codes::decompress_buffer(& buffer_repaired, 1).unwrap() == message);
+                assert!(damaged >= 1);
+            }
+            Err(Error::Unrepairable { damaged, parity }) => assert!(damaged > parity, "...{}% of parity", percent),
+            Err(error) => panic!("...{}% of parity gives {}", percent, error),
+        }
+    }
+}
+
+#[test]
+fn too_much_damage_recovers_the_intact_blocks() {
+    let (message, buffer_johnny) = compressed_message();
+    let buffer_recovery = recovery::add_recovery_record(& buffer_johnny, 5);
+    // The first half of the blocks, and the block headers with them.
+    let mut buffer_damaged = buffer_recovery.clone();
+    for pos in (20..buffer_johnny.len() / 2).step_by(31) {
+        buffer_damaged[pos] ^= 0x5A;
+    }
+    match recovery::repair(& buffer_damaged) {
+        Err(error @ Error::Unrepairable { .. }) => assert_eq!(error.exit_code(), 18),
+        other => panic!("...the damaged file gives {:?}", other.map(|(_, damaged)| damaged)),
+    }
+    let (buffer_partial, damaged_blocks) = recovery::recover_blocks(& buffer_damaged, None, & Limits::NONE).unwrap();
+    assert_eq!(buffer_partial.len(), message.len());
+    assert!(damaged_blocks > 0 && damaged_blocks < 16);
+    // The last block is intact.
+    assert!(buffer_partial[15 * 1024..] == message[15 * 1024..]);
+}
+
+#[test]
+fn recovered_blocks_are_checked_with_the_limits() {
+    let (message, buffer_johnny) = compressed_message();
+    let mut buffer_damaged = recovery::add_recovery_record(& buffer_johnny, 5);
+    for pos in (20..buffer_johnny.len() / 2).step_by(31) {
+        buffer_damaged[pos] ^= 0x5A;
+    }
+    // The block headers of the record claim the whole message, it's checked
+    // before the damaged blocks are filled with zeros.
+    let limits = Limits { max_output: message.len() as u64 - 1, ..Limits::NONE };
+    match recovery::recover_blocks(& buffer_damaged, None, & limits) {
+        Err(error @ Error::LimitExceeded { limit: "output size", .. }) => assert_eq!(error.exit_code(), 12),
+        other => panic!("...the recovery over the limit gives {:?}", other.map(|(_, damaged_blocks)| damaged_blocks)),
+    }
+}
+
+#[test]
+fn encrypted_file_is_repaired_but_has_no_blocks() {
+    let (message, buffer_johnny) = compressed_message();
+    let secret = Secret::Key([3; 32]);
+    let buffer_encrypted = encryption::encrypt_with_params(& buffer_johnny, & secret, & KdfParams::default()).unwrap();
+    let buffer_recovery = recovery::add_recovery_record(& buffer_encrypted, 10);
+    let mut buffer_damaged = buffer_recovery.clone();
+    buffer_damaged[100] ^= 0x01;
+
+    let (buffer_repaired, _) = recovery::repair(& buffer_damaged).unwrap();
+    let buffer_decrypted = encryption::decrypt(recovery::without_recovery_record(& buffer_repaired), & secret).unwrap();
+    assert!(huffman_codes::decompress_buffer(& buffer_decrypted, 1).unwrap() == message);
+    assert!(matches!(recovery::recover_blocks(& buffer_repaired, None, & Limits::NONE), Err(Error::Recovery(_))));
+}
+
+#[test]
+fn damaged_or_missing_record_is_reported() {
+    let (_, buffer_johnny) = compressed_message();
+    assert!(matches!(recovery::repair(& buffer_johnny), Err(Error::Recovery(_))));
+    let mut buffer_recovery = recovery::add_recovery_record(& buffer_johnny, 10);
+    let table_byte = buffer_recovery.len() - 20;
+    buffer_recovery[table_byte] ^= 0x01;
+    match recovery::repair(& buffer_recovery) {
+        Err(error @ Error::Recovery(_)) => assert_eq!(error.exit_code(), 17),
+        other => panic!("...the damaged record gives {:?}", other.map(|(_, damaged)| damaged)),
+    }
+    // A record cut short, or a length that points before the file.
+    let len = buffer_recovery.len();
+    assert!(matches!(recovery::repair(& buffer_recovery[len - 12..]), Err(Error::Recovery(_))));
+}
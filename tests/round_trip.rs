@@ -0,0 +1,277 @@
+// The round trip of the compression and the decompression.
+//
+// Any message, compressed with any of the options, must decompress to the
+// same message. The property tests use arbitrary bytes and options, the
+// other tests the messages that the code treats in a special way: the empty
+// message, the messages of 1 to 3 bytes, a single repeated symbol, all the
+// 256 bytes and very skewed frequencies. A .johnny file is never bigger than
+// the message with the headers. The lengths of the Huffman codes must fill
+// the code space, the Kraft sum of 2^-len is exactly one.
+
+use proptest::prelude::*;
+
+use huffman_codes::alphabet::Alphabet;
+use huffman_codes::coder::{Coder, CoderMode};
+use huffman_codes::dictionary::Dictionary;
+use huffman_codes::filter::{Filter, FilterMode};
+use huffman_codes::CompressOptions;
+
+const ALPHABETS: [Alphabet; 4] = [Alphabet::Bytes, Alphabet::U16, Alphabet::Pairs, Alphabet::Words];
+
+const CODERS: [CoderMode; 4] = [
+    CoderMode::Fixed(Coder::Huffman),
+    CoderMode::Fixed(Coder::Arith),
+    CoderMode::Fixed(Coder::Fse),
+    CoderMode::Auto,
+];
+
+/// Compresses and decompresses the message, it must come back the same.
+fn check_round_trip(message: &[u8], options: & CompressOptions) {
+    huffman_codes::set_quiet(true);
+    let buffer_johnny = huffman_codes::compress_buffer(message, options);
+    let buffer_out = huffman_codes::decompress_buffer(& buffer_johnny, 1)
+        .unwrap_or_else(|error| panic!("...{:?} doesn't decompress: {}", options, error));
+    assert!(buffer_out == message, "...{:?} doesn't round trip a message of {} bytes", options, message.len());
+}
+
+/// The message with every alphabet and coder, in one block and in blocks
+/// of 64 bytes with 3 streams.
+fn check_round_trip_all(message: &[u8]) {
+    for &alphabet in ALPHABETS.iter() {
+        for &coder in CODERS.iter() {
+            for &(block_size, streams) in [(1 << 20, 1), (64, 3)].iter() {
+                let options = CompressOptions { alphabet, coder, block_size, streams, ..CompressOptions::default() };
+                check_round_trip(message, & options);
+            }
+        }
+    }
+}
+
+fn filter_strategy() -> impl Strategy<Value = FilterMode> {
+    prop_oneof![
+        Just(FilterMode::Fixed(Filter::None)),
+        (1_u8..=8).prop_map(|n| FilterMode::Fixed(Filter::Delta(n))),
+        (1_u8..=8).prop_map(|n| FilterMode::Fixed(Filter::Shuffle(n))),
+        Just(FilterMode::Fixed(Filter::X86)),
+        Just(FilterMode::Fixed(Filter::Arm64)),
+        Just(FilterMode::Auto),
+    ]
+}
+
+fn options_strategy() -> impl Strategy<Value = CompressOptions> {
+    (filter_strategy(), 0..ALPHABETS.len(), 0..CODERS.len(), 1_usize..=64, 1_usize..=8, 1_usize..=4)
+        .prop_map(|(filter, alphabet, coder, block_units, streams, threads)| CompressOptions {
+            filter,
+            alphabet: ALPHABETS[alphabet],
+            coder:    CODERS[coder],
+            // Blocks of 16 to 1024 bytes, the messages have many of them.
+            block_size: block_units * 16,
+            streams,
+            threads,
+            dictionary: None,
+        })
+}
+
+/// Bytes of a small alphabet, with a few symbols much more frequent.
+fn skewed_strategy() -> impl Strategy<Value = Vec<u8>> {
+    prop::collection::vec(prop_oneof![8 => Just(0_u8), 3 => Just(1_u8), 1 => any::<u8>()], 0..4096)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(128))]
+
+    #[test]
+    fn arbitrary_bytes_round_trip(message in prop::collection::vec(any::<u8>(), 0..4096),
+                                  options in options_strategy()) {
+        check_round_trip(& message, & options);
+    }
+
+    #[test]
+    fn skewed_bytes_round_trip(message in skewed_strategy(), options in options_strategy()) {
+        check_round_trip(& message, & options);
+    }
+
+    /// No block is bigger than it's stored bytes.
+    #[test]
+    fn compressed_size_is_bounded(message in prop::collection::vec(any::<u8>(), 0..4096), options in options_strategy()) {
+        huffman_codes::set_quiet(true);
+        let (buffer_johnny, stats) = huffman_codes::compress_buffer_with_stats(& message, & options);
+        prop_assert!(buffer_johnny.len() <= message.len() + stats.header_bytes);
+    }
+
+    /// The code lengths of 2 to 256 symbols with any counts.
+    #[test]
+    fn huffman_code_lengths_fill_the_code_space(counts in prop::collection::vec(1_usize..=1 << 20, 2..=256)) {
+        huffman_codes::set_quiet(true);
+        let vec_lengths = huffman_codes::huffman_code_lengths(& counts);
+        prop_assert_eq!(vec_lengths.len(), counts.len());
+        let max_len = vec_lengths.iter().map(|(_symbol, len)| *len).max().unwrap();
+        prop_assert!(max_len <= 64);
+        let kraft_sum: u128 = vec_lengths.iter().map(|(_symbol, len)| 1_u128 << (64 - len)).sum();
+        prop_assert_eq!(kraft_sum, 1_u128 << 64);
+    }
+
+    /// A single symbol has the empty code, the block is a run of it.
+    #[test]
+    fn huffman_code_of_a_single_symbol_is_empty(symbol in 0_usize..256, count in 1_usize..=1 << 20) {
+        huffman_codes::set_quiet(true);
+        let mut counts = vec![0; 256];
+        counts[symbol] = count;
+        prop_assert_eq!(huffman_codes::huffman_code_lengths(& counts), vec![(symbol as u32, 0)]);
+    }
+
+    /// The more frequent of two symbols never has a longer code.
+    #[test]
+    fn huffman_code_lengths_follow_the_counts(counts in prop::collection::vec(1_usize..=1000, 2..=256)) {
+        huffman_codes::set_quiet(true);
+        let vec_lengths = huffman_codes::huffman_code_lengths(& counts);
+        for (symbol_a, len_a) in vec_lengths.iter() {
+            for (symbol_b, len_b) in vec_lengths.iter() {
+                if counts[*symbol_a as usize] > counts[*symbol_b as usize] {
+                    prop_assert!(len_a <= len_b);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn empty_message_round_trips() {
+    check_round_trip_all(&[]);
+}
+
+#[test]
+fn every_johnny_file_has_the_header() {
+    huffman_codes::set_quiet(true);
+    for message in [&b""[..], b"a", b"ab", b"abc"].iter() {
+        let buffer_johnny = huffman_codes::compress_buffer(message, & CompressOptions::default());
+        assert!(buffer_johnny.starts_with(b"JHNY"));
+    }
+    // Raw bytes are never taken for a .johnny file.
+    for buffer in [&b""[..], b"a", b"ab", b"JHN"].iter() {
+        assert!(huffman_codes::decompress_buffer(buffer, 1).is_err());
+    }
+}
+
+#[test]
+fn messages_of_1_to_3_bytes_round_trip() {
+    for message in [&b"a"[..], b"ab", b"abc", b"aaa", b"\x00\xFF", b"\xE8\x00\x00"].iter() {
+        check_round_trip_all(message);
+    }
+}
+
+#[test]
+fn single_repeated_symbol_round_trips() {
+    for &len in [1, 2, 3, 4, 5, 1000, 10_000].iter() {
+        check_round_trip_all(& vec![b'x'; len]);
+    }
+}
+
+/// A run has the same size for any length within a block, and each more
+/// block of the run adds the same number of bytes.
+#[test]
+fn single_repeated_symbol_has_a_constant_size_per_block() {
+    huffman_codes::set_quiet(true);
+    let options = CompressOptions::default();
+    let buffer_short = huffman_codes::compress_buffer(& vec![b'x'; 1000], & options);
+    let buffer_long = huffman_codes::compress_buffer(& vec![b'x'; 1_000_000], & options);
+    assert_eq!(buffer_short.len(), buffer_long.len());
+
+    let options = CompressOptions { block_size: 1024, ..CompressOptions::default() };
+    let size_of = |num_blocks: usize| huffman_codes::compress_buffer(& vec![b'x'; num_blocks * 1024], & options).len();
+    let block_size = size_of(2) - size_of(1);
+    assert_eq!(size_of(3) - size_of(2), block_size);
+    assert_eq!(size_of(10) - size_of(1), 9 * block_size);
+}
+
+/// The library keeps the number of streams in the ones the format has.
+#[test]
+fn any_number_of_streams_round_trips() {
+    let message: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+    for &streams in [0, 16, 17, 256, 300].iter() {
+        check_round_trip(& message, & CompressOptions { streams, ..CompressOptions::default() });
+    }
+}
+
+/// Short messages coded with a dictionary trained on a similar sample, the
+/// blocks only have the ID of the dictionary and the streams.
+#[test]
+fn messages_with_a_dictionary_round_trip() {
+    huffman_codes::set_quiet(true);
+    let sample: Vec<u8> = b"the quick brown fox jumps over the lazy dog. ".iter().cycle().take(4096).copied().collect();
+    let dictionary = Dictionary::train(& sample);
+    for &coder in [CoderMode::Fixed(Coder::Huffman), CoderMode::Auto].iter() {
+        for message in [&b"a"[..], b"the lazy fox", b"\x00\xFF", & sample[..1000]].iter() {
+            let options = CompressOptions { coder, block_size: 256, dictionary: Some(dictionary.clone()), ..CompressOptions::default() };
+            let buffer_johnny = huffman_codes::compress_buffer(message, & options);
+            let buffer_out = huffman_codes::decompress_buffer_with_dictionary(& buffer_johnny, 1, Some(& dictionary))
+                .unwrap_or_else(|error| panic!("...{:?} doesn't decompress: {}", coder, error));
+            assert!(buffer_out == *message, "...{:?} doesn't round trip a message of {} bytes", coder, message.len());
+        }
+    }
+    // The coder of the block header after the 15 bytes of the .johnny header,
+    // the body after the 10 bytes of the block header is the 4 bytes of the
+    // ID and the streams, with their number in the first byte.
+    let options = CompressOptions { dictionary: Some(dictionary.clone()), ..CompressOptions::default() };
+    let buffer_johnny = huffman_codes::compress_buffer(& sample[..1000], & options);
+    assert_eq!(buffer_johnny[16], Coder::Dict.to_header_byte());
+    assert_eq!(& buffer_johnny[25..29], & dictionary.id.to_be_bytes());
+    assert_eq!(buffer_johnny[29], 1);
+}
+
+#[test]
+fn all_256_bytes_round_trip() {
+    let message: Vec<u8> = (0..=255).collect();
+    check_round_trip_all(& message);
+    let message: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+    check_round_trip_all(& message);
+}
+
+/// One byte of each value and the rest zeros, the rare bytes get the longest
+/// codes, and Fibonacci counts, that give the deepest Huffman tree for the
+/// number of symbols.
+fn skewed_messages() -> Vec<Vec<u8>> {
+    let mut message_zeros: Vec<u8> = (0..=255).collect();
+    message_zeros.extend(std::iter::repeat_n(0, 20_000));
+    let mut message_fibonacci: Vec<u8> = Vec::new();
+    let (mut count_a, mut count_b) = (1_usize, 1_usize);
+    for symbol in 0..20_u8 {
+        message_fibonacci.extend(std::iter::repeat_n(symbol, count_a));
+        let next = count_a + count_b;
+        count_a = count_b;
+        count_b = next;
+    }
+    vec![message_zeros, message_fibonacci]
+}
+
+#[test]
+fn highly_skewed_frequencies_round_trip() {
+    for message in skewed_messages() {
+        check_round_trip_all(& message);
+    }
+}
+
+#[test]
+fn mapping_table_bigger_than_64_kib_round_trips() {
+    huffman_codes::set_quiet(true);
+    // All the 65536 symbols of the u16 alphabet once, and many zeros so the
+    // block still compresses, the table has 3 bytes for each symbol.
+    let mut message: Vec<u8> = (0..=0xFFFF_u16).flat_map(u16::to_le_bytes).collect();
+    message.extend(std::iter::repeat_n(0, 2_000_000));
+    let options = CompressOptions { alphabet: Alphabet::U16, ..CompressOptions::default() };
+    let (buffer_johnny, stats) = huffman_codes::compress_buffer_with_stats(& message, & options);
+    assert!(stats.table_bytes > 0xFFFF);
+    assert!(huffman_codes::decompress_buffer(& buffer_johnny, 1).unwrap() == message);
+}
+
+#[test]
+fn table_offset_beyond_the_block_is_rejected() {
+    // The offset of the end of the table, 32 bits, the width of the symbols,
+    // the run of one symbol and the number of symbols.
+    let mut buffer_table = vec![0, 0, 0, 7, 1, b'a', 0, 0, 0, 0, 0, 0, 0, 0, 3];
+    assert_eq!(huffman_codes::parse_huffman_table(& buffer_table).unwrap(), 7);
+    buffer_table[3] = 16;
+    assert!(huffman_codes::parse_huffman_table(& buffer_table).is_err());
+    buffer_table[0] = 0x80;
+    assert!(huffman_codes::parse_huffman_table(& buffer_table).is_err());
+}
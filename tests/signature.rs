@@ -0,0 +1,71 @@
+// The signature blocks of the .johnny files, plain and encrypted.
+
+use huffman_codes::encryption::{self, KdfParams, Secret};
+use huffman_codes::error::Error;
+use huffman_codes::signature::{self, SIGNATURE_BLOCK_SIZE};
+use huffman_codes::CompressOptions;
+
+fn compressed_message() -> (Vec<u8>, Vec<u8>) {
+    huffman_codes::set_quiet(true);
+    let message: Vec<u8> = b"firmware = 2.4.1\n".iter().cycle().take(5000).copied().collect();
+    let buffer_johnny = huffman_codes::compress_buffer(& message, & CompressOptions::default());
+    (message, buffer_johnny)
+}
+
+#[test]
+fn signed_file_verifies_and_decompresses() {
+    let (message, buffer_johnny) = compressed_message();
+    let (signing_key, verifying_key) = signature::generate_keys();
+    assert_eq!(signature::verifying_key(& signing_key), verifying_key);
+
+    let buffer_signed = signature::sign(& buffer_johnny, & signing_key).unwrap();
+    assert_eq!(buffer_signed.len(), buffer_johnny.len() + SIGNATURE_BLOCK_SIZE);
+    assert!(signature::verify(& buffer_signed, & verifying_key).unwrap() == & buffer_johnny[..]);
+    // The decoder doesn't read after the last block.
+    assert!(huffman_codes::decompress_buffer(& buffer_signed, 1).unwrap() == message);
+}
+
+#[test]
+fn any_changed_byte_fails_the_verification() {
+    let (_, buffer_johnny) = compressed_message();
+    let (signing_key, verifying_key) = signature::generate_keys();
+    let buffer_signed = signature::sign(& buffer_johnny, & signing_key).unwrap();
+    // The header, a table, the coded data and the signature.
+    let signature_start = buffer_johnny.len();
+    for &pos in [2, 20, signature_start / 2, signature_start - 1, signature_start, signature_start + 63].iter() {
+        let mut buffer_changed = buffer_signed.clone();
+        buffer_changed[pos] ^= 0x01;
+        match signature::verify(& buffer_changed, & verifying_key) {
+            Err(error @ Error::Signature(_)) => assert_eq!(error.exit_code(), 16),
+            other => panic!("...the byte {} changed gives {:?}", pos, other.map(|signed| signed.len())),
+        }
+    }
+}
+
+#[test]
+fn another_key_or_no_signature_is_rejected() {
+    let (_, buffer_johnny) = compressed_message();
+    let (signing_key, _) = signature::generate_keys();
+    let (_, other_verifying_key) = signature::generate_keys();
+    let buffer_signed = signature::sign(& buffer_johnny, & signing_key).unwrap();
+    assert!(matches!(signature::verify(& buffer_signed, & other_verifying_key), Err(Error::Signature(_))));
+    assert!(matches!(signature::verify(& buffer_johnny, & other_verifying_key), Err(Error::Signature(_))));
+    assert!(matches!(signature::sign(& buffer_signed, & signing_key), Err(Error::Signature(_))));
+}
+
+#[test]
+fn encrypted_file_is_signed_after_the_encryption() {
+    let (message, buffer_johnny) = compressed_message();
+    let secret = Secret::Key([9; 32]);
+    let kdf_params = KdfParams::default();
+    let buffer_encrypted = encryption::encrypt_with_params(& buffer_johnny, & secret, & kdf_params).unwrap();
+    let (signing_key, verifying_key) = signature::generate_keys();
+    let buffer_signed = signature::sign(& buffer_encrypted, & signing_key).unwrap();
+
+    let signed = signature::verify(& buffer_signed, & verifying_key).unwrap();
+    let buffer_decrypted = encryption::decrypt(signed, & secret).unwrap();
+    assert!(huffman_codes::decompress_buffer(& buffer_decrypted, 1).unwrap() == message);
+    // The envelope authenticates all of it's bytes, not the signature block.
+    assert!(encryption::decrypt(& buffer_signed, & secret).is_err());
+    assert!(encryption::decrypt(signature::unsigned_part(& buffer_signed), & secret).is_ok());
+}